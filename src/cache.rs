@@ -0,0 +1,82 @@
+use crate::_Span;
+use std::{collections::HashMap, fmt, sync::Arc};
+
+/// An ariadne cache that identifies sources by their real paths, but shows
+/// them using the names chosen by the report configuration.
+#[derive(Default)]
+pub struct SourceCache {
+    sources: HashMap<Arc<str>, (ariadne::Source<Arc<str>>, String)>,
+    texts: HashMap<Arc<str>, Arc<str>>,
+}
+
+impl SourceCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, id: Arc<str>, text: Arc<str>, display: String) {
+        self.texts.insert(id.clone(), text.clone());
+        self.sources
+            .insert(id, (ariadne::Source::from(text), display));
+    }
+
+    pub fn text(&self, id: &Arc<str>) -> Option<&str> {
+        self.texts.get(id).map(|text| &**text)
+    }
+
+    /// Clamp insertion points to the end of the file.
+    ///
+    /// If the file ends with a newline, insertion points at EOF are moved to
+    /// the end of the last line, so they are drawn like other line-end
+    /// insertion points.
+    pub fn insertion_point(&self, span: _Span, byte_indexed: bool) -> _Span {
+        let (id, range) = span;
+        let Some(text) = self.text(&id).filter(|_| range.is_empty()) else {
+            return (id, range);
+        };
+        let len = match byte_indexed {
+            true => text.len(),
+            false => text.chars().count(),
+        };
+        let last = len - usize::from(text.ends_with('\n'));
+        let offset = range.start.min(last);
+        (id, offset..offset)
+    }
+
+    /// Format the start of a span as `path:line:col`, like ariadne headers.
+    pub fn location(&self, span: &_Span, byte_indexed: bool) -> String {
+        let (id, span) = span;
+        let Some((source, display)) = self.sources.get(id) else {
+            return format!("{}:?:?", id);
+        };
+        let line = match byte_indexed {
+            false => source.get_offset_line(span.start),
+            true => source.get_byte_line(span.start).map(|(line, idx, col)| {
+                let text = source.get_line_text(line).unwrap_or_default();
+                (line, idx, text[..col.min(text.len())].chars().count())
+            }),
+        };
+        match line {
+            Some((_, idx, col)) => format!("{}:{}:{}", display, idx + 1, col + 1),
+            None => format!("{}:?:?", display),
+        }
+    }
+}
+
+impl ariadne::Cache<Arc<str>> for SourceCache {
+    type Storage = Arc<str>;
+
+    fn fetch(&mut self, id: &Arc<str>) -> Result<&ariadne::Source<Arc<str>>, impl fmt::Debug> {
+        match self.sources.get(id) {
+            Some((source, _)) => Ok(source),
+            None => Err(format!("Failed to fetch source '{}'", id)),
+        }
+    }
+
+    fn display<'a>(&self, id: &'a Arc<str>) -> Option<impl fmt::Display + 'a> {
+        match self.sources.get(id) {
+            Some((_, display)) => Some(display.clone()),
+            None => Some(id.to_string()),
+        }
+    }
+}
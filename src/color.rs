@@ -1,8 +1,12 @@
 use ariadne::Color::*;
-use pyo3::{exceptions::PyTypeError, prelude::*, types::PyString};
+use pyo3::{
+    exceptions::PyTypeError,
+    prelude::*,
+    types::{PyString, PyTuple},
+};
 use std::hash::Hash;
 
-#[pyclass(frozen, eq, hash)]
+#[pyclass(module = "theseus", frozen, eq, hash)]
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Color {
     pub(crate) inner: ariadne::Color,
@@ -42,6 +46,30 @@ impl Color {
     fn new_fixed(id: u8) -> Self {
         Self::new(Fixed(id))
     }
+
+    /// Name accepted by the constructor, for the named colors.
+    fn name(&self) -> Option<&'static str> {
+        match self.inner {
+            Fixed(_) | Rgb(..) => None,
+            Primary => Some("primary"),
+            Black => Some("black"),
+            Red => Some("red"),
+            Green => Some("green"),
+            Yellow => Some("yellow"),
+            Blue => Some("blue"),
+            Magenta => Some("magenta"),
+            Cyan => Some("cyan"),
+            White => Some("white"),
+            BrightBlack => Some("bright-black"),
+            BrightRed => Some("bright-red"),
+            BrightGreen => Some("bright-green"),
+            BrightYellow => Some("bright-yellow"),
+            BrightBlue => Some("bright-blue"),
+            BrightMagenta => Some("bright-magenta"),
+            BrightCyan => Some("bright-cyan"),
+            BrightWhite => Some("bright-white"),
+        }
+    }
 }
 
 #[pymethods]
@@ -64,6 +92,18 @@ impl Color {
         }
     }
 
+    fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<(Bound<'py, PyAny>, Bound<'py, PyTuple>)> {
+        let cls = py.get_type::<Color>();
+        match (self.inner, self.name()) {
+            (Rgb(r, g, b), _) => Ok((cls.getattr("rgb")?, (r, g, b).into_pyobject(py)?)),
+            (Fixed(id), _) => Ok((cls.into_any(), PyTuple::new(py, [id])?)),
+            (_, name) => Ok((cls.into_any(), PyTuple::new(py, [name])?)),
+        }
+    }
+
     pub fn __repr__(&self) -> String {
         self.__str__()
     }
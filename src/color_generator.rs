@@ -2,9 +2,56 @@ use crate::color::Color;
 use ariadne as rs;
 use pyo3::prelude::*;
 
-#[pyclass]
+/// A sequence of distinct colors that can be cloned.
+///
+/// ariadne's generator keeps its state private, so we count the generated
+/// colors instead, and rebuild the state by generating them again.
+pub struct ColorSequence {
+    inner: rs::ColorGenerator,
+    count: usize,
+}
+
+impl ColorSequence {
+    pub fn new() -> Self {
+        Self::at(0)
+    }
+
+    /// The sequence after generating count colors.
+    pub fn at(count: usize) -> Self {
+        let mut inner = rs::ColorGenerator::new();
+        for _ in 0..count {
+            inner.next();
+        }
+        ColorSequence { inner, count }
+    }
+
+    /// Number of colors generated so far.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> rs::Color {
+        self.count += 1;
+        self.inner.next()
+    }
+}
+
+impl Default for ColorSequence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for ColorSequence {
+    fn clone(&self) -> Self {
+        Self::at(self.count)
+    }
+}
+
+#[pyclass(module = "theseus")]
 pub struct ColorGenerator {
-    pub(crate) inner: rs::ColorGenerator,
+    pub(crate) inner: ColorSequence,
 }
 
 #[pymethods]
@@ -12,7 +59,7 @@ impl ColorGenerator {
     #[new]
     fn new() -> Self {
         ColorGenerator {
-            inner: rs::ColorGenerator::new(),
+            inner: ColorSequence::new(),
         }
     }
 
@@ -27,4 +74,31 @@ impl ColorGenerator {
     fn next(&mut self) -> Color {
         self.__next__()
     }
+
+    // Generators are restored from the number of colors they generated
+    fn __getstate__(&self) -> usize {
+        self.inner.count()
+    }
+
+    fn __setstate__(&mut self, count: usize) {
+        self.inner = ColorSequence::at(count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clones_continue_the_sequence() {
+        let mut colors = ColorSequence::new();
+        for _ in 0..10 {
+            colors.next();
+        }
+        let mut clone = colors.clone();
+        assert_eq!(clone.count(), 10);
+        for _ in 0..10 {
+            assert_eq!(clone.next(), colors.next());
+        }
+    }
 }
@@ -1,15 +1,173 @@
-use ariadne::{CharSet, LabelAttach, IndexType};
-use pyo3::{exceptions::PyValueError, prelude::*};
+use ariadne::{CharSet, IndexType, LabelAttach};
+use pyo3::{
+    exceptions::{PyTypeError, PyValueError},
+    prelude::*,
+    types::{PyDict, PyString, PyTuple},
+};
+use std::sync::Arc;
 
-#[pyclass(eq)]
+#[pyclass(module = "theseus", eq)]
 #[derive(Clone, Debug, PartialEq)]
 pub struct Config {
-    pub(crate) inner: ariadne::Config,
+    // ariadne keeps its options private, so we keep ours and build its
+    // configuration from them when rendering
+    pub(crate) cross_gap: bool,
+    pub(crate) underlines: bool,
+    pub(crate) multiline_arrows: bool,
+    pub(crate) tab_width: usize,
+    pub(crate) label_attach: LabelAttach,
+    pub(crate) compact: bool,
+    pub(crate) color: bool,
+    pub(crate) ascii: bool,
+    pub(crate) byte_indexed: bool,
+    pub(crate) path_display: PathDisplay,
+    pub(crate) show_generated: bool,
+    pub(crate) expansion_limit: Option<usize>,
+}
+
+impl Default for Config {
+    /// The defaults of ariadne, used when no config is given.
+    fn default() -> Self {
+        Config {
+            cross_gap: true,
+            underlines: true,
+            multiline_arrows: true,
+            tab_width: 4,
+            label_attach: LabelAttach::Middle,
+            compact: false,
+            color: true,
+            ascii: false,
+            byte_indexed: false,
+            path_display: PathDisplay::Full,
+            show_generated: false,
+            expansion_limit: None,
+        }
+    }
 }
 
 impl Config {
-    pub fn new(inner: ariadne::Config) -> Self {
-        Config { inner }
+    /// The ariadne configuration for these options.
+    pub fn inner(&self) -> ariadne::Config {
+        let char_set = match self.ascii {
+            true => CharSet::Ascii,
+            false => CharSet::Unicode,
+        };
+        let index_type = match self.byte_indexed {
+            true => IndexType::Byte,
+            false => IndexType::Char,
+        };
+        ariadne::Config::default()
+            .with_cross_gap(self.cross_gap)
+            .with_compact(self.compact)
+            .with_underlines(self.underlines)
+            .with_multiline_arrows(self.multiline_arrows)
+            .with_color(self.color)
+            .with_tab_width(self.tab_width)
+            .with_char_set(char_set)
+            .with_index_type(index_type)
+            .with_label_attach(self.label_attach)
+    }
+}
+
+/// Controls how the paths of source files are shown in the rendered report.
+///
+/// This only changes the name printed in the report header, files are still
+/// loaded and matched using their real paths.
+#[derive(Clone, Debug)]
+pub enum PathDisplay {
+    Full,
+    Relative(Option<Arc<str>>),
+    Basename,
+    Uri,
+    // Py<T> is only clonable with the py-clone feature, so we share it with an Arc
+    Custom(Arc<PyObject>),
+}
+
+impl PathDisplay {
+    fn from_params(
+        policy: Option<&Bound<'_, PyAny>>,
+        root: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Self> {
+        let root: Option<Arc<str>> = match root {
+            Some(root) => Some(root.str()?.to_str()?.into()),
+            None => None,
+        };
+        let Some(policy) = policy else {
+            // A root without an explicit policy can only mean relative paths
+            return Ok(match root {
+                Some(root) => PathDisplay::Relative(Some(root)),
+                None => PathDisplay::Full,
+            });
+        };
+        if let Ok(name) = policy.downcast::<PyString>() {
+            let policy = match name.to_str()? {
+                "full" => PathDisplay::Full,
+                "relative" => PathDisplay::Relative(root.clone()),
+                "basename" => PathDisplay::Basename,
+                "uri" => PathDisplay::Uri,
+                _ => {
+                    let msg = "path_display must be one of 'full', 'relative', 'basename', 'uri' or a callable";
+                    return Err(PyValueError::new_err(msg));
+                }
+            };
+            if root.is_some() && !matches!(policy, PathDisplay::Relative(_)) {
+                let msg = "path_root can only be used with path_display='relative'";
+                return Err(PyValueError::new_err(msg));
+            }
+            Ok(policy)
+        } else if policy.is_callable() {
+            if root.is_some() {
+                let msg = "path_root can only be used with path_display='relative'";
+                return Err(PyValueError::new_err(msg));
+            }
+            Ok(PathDisplay::Custom(Arc::new(policy.clone().unbind())))
+        } else {
+            let msg = "path_display must be a string or a callable";
+            Err(PyTypeError::new_err(msg))
+        }
+    }
+
+    /// Return the name used to display the given path in a report.
+    pub fn display(&self, py: Python<'_>, path: &str) -> PyResult<String> {
+        match self {
+            PathDisplay::Full => Ok(path.into()),
+            PathDisplay::Relative(root) => {
+                let os_path = PyModule::import(py, "os.path")?;
+                let root = match root {
+                    Some(root) => root.to_string(),
+                    None => os_path.call_method1("abspath", (".",))?.extract()?,
+                };
+                // relpath() fails for paths in different drives on Windows
+                match os_path.call_method1("relpath", (path, root)) {
+                    Ok(relative) => relative.extract(),
+                    Err(_) => Ok(path.into()),
+                }
+            }
+            PathDisplay::Basename => {
+                let os_path = PyModule::import(py, "os.path")?;
+                let name: String = os_path.call_method1("basename", (path,))?.extract()?;
+                Ok(if name.is_empty() { path.into() } else { name })
+            }
+            PathDisplay::Uri => {
+                let path_type = PyModule::import(py, "pathlib")?.getattr("Path")?;
+                let path = path_type.call1((path,))?.call_method0("absolute")?;
+                path.call_method0("as_uri")?.extract()
+            }
+            PathDisplay::Custom(func) => func.bind(py).call1((path,))?.str()?.extract(),
+        }
+    }
+}
+
+impl PartialEq for PathDisplay {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (PathDisplay::Full, PathDisplay::Full) => true,
+            (PathDisplay::Relative(a), PathDisplay::Relative(b)) => a == b,
+            (PathDisplay::Basename, PathDisplay::Basename) => true,
+            (PathDisplay::Uri, PathDisplay::Uri) => true,
+            (PathDisplay::Custom(a), PathDisplay::Custom(b)) => a.is(b.as_ref()),
+            _ => false,
+        }
     }
 }
 
@@ -27,7 +185,11 @@ impl Config {
             tab_width=4,
             ascii=false,
             byte_indexed=false,
-            label_attach="middle")
+            label_attach="middle",
+            path_display=None,
+            path_root=None,
+            show_generated=false,
+            expansion_limit=None)
     )]
     #[allow(clippy::too_many_arguments)]
     pub(crate) fn py_new(
@@ -40,34 +202,67 @@ impl Config {
         ascii: bool,
         byte_indexed: bool,
         label_attach: &str,
+        path_display: Option<&Bound<'_, PyAny>>,
+        path_root: Option<&Bound<'_, PyAny>>,
+        show_generated: bool,
+        expansion_limit: Option<usize>,
     ) -> PyResult<Self> {
-        let inner = ariadne::Config::default()
-            .with_cross_gap(cross_gap)
-            .with_compact(compact)
-            .with_underlines(underlines)
-            .with_multiline_arrows(multiline_arrows)
-            .with_color(color)
-            .with_tab_width(tab_width)
-            .with_char_set(
-                if ascii {
-                    CharSet::Ascii
-                } else {
-                    CharSet::Unicode
-                }
-            )
-            .with_index_type(
-                if byte_indexed {
-                    IndexType::Byte 
-                } else { 
-                    IndexType::Char 
-                }
-            )
-            .with_label_attach(parse_label_attach(label_attach)?);
-        Ok(Config::new(inner))
+        let path_display = PathDisplay::from_params(path_display, path_root)?;
+        Ok(Config {
+            cross_gap,
+            underlines,
+            multiline_arrows,
+            tab_width,
+            label_attach: parse_label_attach(label_attach)?,
+            compact,
+            color,
+            ascii,
+            byte_indexed,
+            path_display,
+            show_generated,
+            expansion_limit,
+        })
+    }
+
+    fn __getnewargs_ex__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<(Bound<'py, PyTuple>, Bound<'py, PyDict>)> {
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("cross_gap", self.cross_gap)?;
+        kwargs.set_item("compact", self.compact)?;
+        kwargs.set_item("underlines", self.underlines)?;
+        kwargs.set_item("multiline_arrows", self.multiline_arrows)?;
+        kwargs.set_item("color", self.color)?;
+        kwargs.set_item("tab_width", self.tab_width)?;
+        kwargs.set_item("ascii", self.ascii)?;
+        kwargs.set_item("byte_indexed", self.byte_indexed)?;
+        kwargs.set_item("label_attach", label_attach_name(self.label_attach))?;
+        match &self.path_display {
+            PathDisplay::Full => {}
+            PathDisplay::Relative(root) => {
+                kwargs.set_item("path_display", "relative")?;
+                kwargs.set_item("path_root", root.as_deref())?;
+            }
+            PathDisplay::Basename => kwargs.set_item("path_display", "basename")?,
+            PathDisplay::Uri => kwargs.set_item("path_display", "uri")?,
+            PathDisplay::Custom(func) => kwargs.set_item("path_display", func.bind(py))?,
+        }
+        kwargs.set_item("show_generated", self.show_generated)?;
+        kwargs.set_item("expansion_limit", self.expansion_limit)?;
+        Ok((PyTuple::empty(py), kwargs))
     }
 
     fn __str__(&self) -> String {
-        format!("{:?}", self.inner)
+        format!("{:?}", self.inner())
+    }
+}
+
+fn label_attach_name(label_attach: LabelAttach) -> &'static str {
+    match label_attach {
+        LabelAttach::Start => "start",
+        LabelAttach::End => "middle",
+        LabelAttach::Middle => "right",
     }
 }
 
@@ -0,0 +1,301 @@
+use crate::source::{Decoding, Source};
+use crate::span::SpanArg;
+use pyo3::exceptions::{PySyntaxError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::sync::GILOnceCell;
+use pyo3::types::{PyDict, PyTuple, PyType};
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Converters registered with `register_exception_converter`, indexed by type.
+static CONVERTERS: GILOnceCell<Py<PyDict>> = GILOnceCell::new();
+
+/// The parts of a report extracted from an exception.
+pub struct Converted {
+    pub(crate) source: Source,
+    pub(crate) span: Range<usize>,
+    pub(crate) message: String,
+    pub(crate) expected: Vec<String>,
+}
+
+impl Converted {
+    /// Help text listing the expected tokens, if any.
+    pub fn help(&self) -> Option<String> {
+        match self.expected.as_slice() {
+            [] => None,
+            [token] => Some(format!("expected {token}")),
+            tokens => Some(format!("expected one of: {}", tokens.join(", "))),
+        }
+    }
+}
+
+/// Convert an exception using a registered converter, or the built-in
+/// converters for SyntaxError, lark and pyparsing exceptions.
+///
+/// Libraries are recognized by module name, so they are never imported.
+pub fn convert(
+    exc: &Bound<'_, PyAny>,
+    source: Option<&Bound<'_, PyAny>>,
+    decoding: &Decoding,
+    byte_indexed: bool,
+) -> PyResult<Converted> {
+    let source = match source {
+        Some(source) => Some(Source::from_python(source, decoding)?),
+        None => None,
+    };
+    if let Some(converter) = registered_converter(exc)? {
+        return from_converter(exc, &converter, source, byte_indexed);
+    }
+    if exc.is_instance_of::<PySyntaxError>() {
+        return from_syntax_error(exc, source, decoding, byte_indexed);
+    }
+    let module: String = exc.get_type().getattr("__module__")?.extract()?;
+    match module.split('.').next().unwrap_or_default() {
+        "lark" => from_lark(exc, require_source(exc, source)?, byte_indexed),
+        "pyparsing" => from_pyparsing(exc, source, byte_indexed),
+        _ => {
+            let name = exc.get_type().name()?;
+            let msg = format!("No converter for {name}, see register_exception_converter()");
+            Err(PyTypeError::new_err(msg))
+        }
+    }
+}
+
+fn registered_converter<'py>(exc: &Bound<'py, PyAny>) -> PyResult<Option<Bound<'py, PyAny>>> {
+    let Some(converters) = CONVERTERS.get(exc.py()) else {
+        return Ok(None);
+    };
+    let converters = converters.bind(exc.py());
+    for cls in exc.get_type().mro() {
+        if let Some(converter) = converters.get_item(cls)? {
+            return Ok(Some(converter));
+        }
+    }
+    Ok(None)
+}
+
+fn require_source(exc: &Bound<'_, PyAny>, source: Option<Source>) -> PyResult<Source> {
+    match source {
+        Some(source) => Ok(source),
+        None => {
+            let name = exc.get_type().name()?;
+            let msg = format!("A source is required to convert {name}");
+            Err(PyValueError::new_err(msg))
+        }
+    }
+}
+
+/// Converters return (span, message) or (span, message, expected) tuples.
+fn from_converter(
+    exc: &Bound<'_, PyAny>,
+    converter: &Bound<'_, PyAny>,
+    source: Option<Source>,
+    byte_indexed: bool,
+) -> PyResult<Converted> {
+    let source = require_source(exc, source)?;
+    let result = converter.call1((exc,))?;
+    let result = result.downcast::<PyTuple>()?;
+    let (span, message, expected) = match result.len() {
+        2 => (
+            result.get_item(0)?,
+            result.get_item(1)?.extract()?,
+            Vec::new(),
+        ),
+        3 => {
+            let expected = result.get_item(2)?;
+            let expected = match expected.is_none() {
+                true => Vec::new(),
+                false => sorted(
+                    expected
+                        .try_iter()?
+                        .map(|token| token?.extract())
+                        .collect::<PyResult<_>>()?,
+                ),
+            };
+            (
+                result.get_item(0)?,
+                result.get_item(1)?.extract()?,
+                expected,
+            )
+        }
+        _ => {
+            let msg = "Converters must return (span, message) or (span, message, expected) tuples";
+            return Err(PyTypeError::new_err(msg));
+        }
+    };
+    let span = SpanArg::from_object(&span)?.resolve(Some(&source), byte_indexed);
+    Ok(Converted {
+        source,
+        span,
+        message,
+        expected,
+    })
+}
+
+/// SyntaxError columns are 1-based char offsets and the end is exclusive.
+///
+/// Without a source, the file is read from the exception filename or, if it
+/// is not available, the offending line is shown on its own.
+fn from_syntax_error(
+    exc: &Bound<'_, PyAny>,
+    source: Option<Source>,
+    decoding: &Decoding,
+    byte_indexed: bool,
+) -> PyResult<Converted> {
+    let message: String = exc.getattr("msg")?.extract()?;
+    let line: usize = exc
+        .getattr("lineno")?
+        .extract::<Option<usize>>()?
+        .unwrap_or(1);
+    let col: usize = exc
+        .getattr("offset")?
+        .extract::<Option<usize>>()?
+        .unwrap_or(1);
+    let end_line: Option<usize> = exc.getattr("end_lineno")?.extract()?;
+    let end_col: Option<usize> = exc.getattr("end_offset")?.extract()?;
+
+    let (source, first_line) = match source {
+        Some(source) => (source, 1),
+        None => syntax_error_source(exc, line, decoding)?,
+    };
+    let start = source.line_col(line + 1 - first_line, col.saturating_sub(1), false);
+    let end = match (end_line, end_col) {
+        (Some(end_line), Some(end_col)) if end_col > 0 => {
+            source.line_col(end_line + 1 - first_line, end_col - 1, false)
+        }
+        _ => source.line_col(line + 1 - first_line, col, false),
+    };
+    let span = source.original_span(start..end.max(start), byte_indexed);
+    Ok(Converted {
+        source,
+        span,
+        message,
+        expected: Vec::new(),
+    })
+}
+
+fn syntax_error_source(
+    exc: &Bound<'_, PyAny>,
+    line: usize,
+    decoding: &Decoding,
+) -> PyResult<(Source, usize)> {
+    let py = exc.py();
+    let filename: Option<String> = exc.getattr("filename")?.extract()?;
+    if let Some(filename) = &filename {
+        let path = PyModule::import(py, "pathlib")?
+            .getattr("Path")?
+            .call1((filename,))?;
+        if path.call_method0("is_file")?.is_truthy()? {
+            return Ok((Source::from_python(&path, decoding)?, 1));
+        }
+    }
+    let Some(text) = exc.getattr("text")?.extract::<Option<String>>()? else {
+        let msg = "A source is required to convert a SyntaxError without text";
+        return Err(PyValueError::new_err(msg));
+    };
+    let path: Arc<str> = filename.as_deref().unwrap_or("<unknown>").into();
+    let source = Source::new(path, &text).with_position(line.saturating_sub(1), 0);
+    Ok((source, line.max(1)))
+}
+
+/// Lark exceptions point to the unexpected token or character, in chars.
+fn from_lark(exc: &Bound<'_, PyAny>, source: Source, byte_indexed: bool) -> PyResult<Converted> {
+    let name = exc.get_type().name()?.to_string();
+    let token = exc.getattr("token").ok().filter(|token| !token.is_none());
+    let at_end = match &token {
+        Some(token) => token.getattr("type")?.extract::<String>()? == "$END",
+        None => name == "UnexpectedEOF",
+    };
+    let pos: Option<isize> = exc
+        .getattr("pos_in_stream")
+        .ok()
+        .and_then(|pos| pos.extract().ok());
+
+    let chars = match (&token, pos) {
+        _ if at_end => None,
+        (Some(token), _) if !token.getattr("start_pos")?.is_none() => {
+            let start: usize = token.getattr("start_pos")?.extract()?;
+            Some(start..token.getattr("end_pos")?.extract()?)
+        }
+        (_, Some(pos)) if pos >= 0 => Some(pos as usize..pos as usize + 1),
+        _ => None,
+    };
+    let span = match chars {
+        Some(chars) => source.convert_span(chars, false, byte_indexed),
+        None => source.original_span(source.text.len()..source.text.len(), byte_indexed),
+    };
+
+    let message = match (&token, at_end) {
+        (_, true) => "Unexpected end of input".to_string(),
+        (Some(token), _) => format!("Unexpected token {:?}", token.str()?.to_str()?),
+        (None, _) => match exc.getattr("char") {
+            Ok(char) => format!("Unexpected character {:?}", char.str()?.to_str()?),
+            Err(_) => "Unexpected input".to_string(),
+        },
+    };
+    let mut expected = Vec::new();
+    for attr in ["expected", "allowed"] {
+        if let Ok(tokens) = exc.getattr(attr) {
+            if !tokens.is_none() {
+                for token in tokens.try_iter()? {
+                    expected.push(token?.str()?.to_string());
+                }
+                break;
+            }
+        }
+    }
+    Ok(Converted {
+        source,
+        span,
+        message,
+        expected: sorted(expected),
+    })
+}
+
+/// Pyparsing exceptions keep the parsed string and the location where
+/// parsing failed, which is shown as an insertion point.
+fn from_pyparsing(
+    exc: &Bound<'_, PyAny>,
+    source: Option<Source>,
+    byte_indexed: bool,
+) -> PyResult<Converted> {
+    let source = match source {
+        Some(source) => source,
+        None => match exc
+            .getattr("pstr")
+            .and_then(|text| text.extract::<String>())
+        {
+            Ok(text) => Source::new("<string>".into(), &text),
+            Err(_) => require_source(exc, None)?,
+        },
+    };
+    let loc: usize = exc.getattr("loc")?.extract()?;
+    let message: String = exc.getattr("msg")?.extract()?;
+    let span = source.convert_span(loc..loc, false, byte_indexed);
+    Ok(Converted {
+        source,
+        span,
+        message,
+        expected: Vec::new(),
+    })
+}
+
+fn sorted(mut tokens: Vec<String>) -> Vec<String> {
+    tokens.sort();
+    tokens.dedup();
+    tokens
+}
+
+/// Register a function that converts instances of cls to reports.
+#[pyfunction]
+pub fn register_exception_converter(
+    cls: &Bound<'_, PyType>,
+    converter: &Bound<'_, PyAny>,
+) -> PyResult<()> {
+    if !converter.is_callable() {
+        let msg = "Exception converters must be callable";
+        return Err(PyTypeError::new_err(msg));
+    }
+    let converters = CONVERTERS.get_or_init(cls.py(), || PyDict::new(cls.py()).unbind());
+    converters.bind(cls.py()).set_item(cls, converter)
+}
@@ -0,0 +1,202 @@
+use crate::config::Config;
+use crate::label::Label;
+use crate::report::{Report, ReportKind};
+use crate::source::{from_path_or_str, Decoding, RawText, Source};
+use crate::suggestion::Suggestion;
+use pyo3::exceptions::{PyFileNotFoundError, PyValueError};
+use pyo3::types::PyDict;
+use pyo3::{prelude::*, IntoPyObjectExt};
+use std::{ops::Range, sync::Arc};
+
+/// A replacement of a span in the original input of a file.
+#[derive(PartialEq)]
+struct Edit {
+    span: Range<usize>,
+    replacement: String,
+}
+
+impl Edit {
+    fn from_python(obj: &Bound<'_, PyAny>) -> PyResult<(Arc<str>, Self)> {
+        let (path, span, replacement) = match obj.downcast::<Suggestion>() {
+            Ok(suggestion) => {
+                let suggestion = suggestion.get();
+                let Some(path) = suggestion.target.clone() else {
+                    let msg = "Suggestions must have a path to be applied";
+                    return Err(PyValueError::new_err(msg));
+                };
+                (
+                    path,
+                    suggestion.span.clone(),
+                    suggestion.replacement.clone(),
+                )
+            }
+            Err(_) => {
+                let (path, start, end, replacement): (Bound<'_, PyAny>, usize, usize, String) =
+                    obj.extract()?;
+                if start > end {
+                    let msg = "Start index must be less than or equal to end index";
+                    return Err(PyValueError::new_err(msg));
+                }
+                (from_path_or_str(&path)?, start..end, replacement)
+            }
+        };
+        Ok((path, Edit { span, replacement }))
+    }
+}
+
+/// Apply text edits to files, grouped by path.
+///
+/// Files with overlapping edits are left untouched and the conflicts are
+/// returned as reports.
+#[pyfunction]
+#[pyo3(signature=(edits, *, mode="text", config=Config::default(), encoding="utf-8", errors="strict"))]
+pub fn apply_edits(
+    py: Python<'_>,
+    edits: Vec<Bound<'_, PyAny>>,
+    mode: &str,
+    config: Config,
+    encoding: &str,
+    errors: &str,
+) -> PyResult<(PyObject, Vec<Report>)> {
+    if !["text", "write", "diff"].contains(&mode) {
+        let msg = format!("Unknown mode: {}", mode);
+        return Err(PyValueError::new_err(msg));
+    }
+    // A lossy decode would replace the bytes it failed on in the whole file
+    if mode == "write" && errors != "strict" {
+        let msg = format!("Files can only be written with errors='strict', not '{errors}'");
+        return Err(PyValueError::new_err(msg));
+    }
+    let decoding = Decoding::new(encoding, errors);
+    let mut files: Vec<(Arc<str>, Vec<Edit>)> = Vec::new();
+    for edit in &edits {
+        let (path, edit) = Edit::from_python(edit)?;
+        match files.iter_mut().find(|(p, _)| *p == path) {
+            Some((_, edits)) => edits.push(edit),
+            None => files.push((path, vec![edit])),
+        }
+    }
+
+    let mut patched = Vec::new();
+    let mut conflicts = Vec::new();
+    for (path, mut edits) in files {
+        let data = std::fs::read(path.as_ref()).map_err(|e| {
+            let msg = format!("Failed to read file '{}': {}", path, e);
+            PyFileNotFoundError::new_err(msg)
+        })?;
+        edits.sort_by_key(|edit| (edit.span.start, edit.span.end));
+        edits.dedup();
+        let found = find_conflicts(&path, &data, &edits, &config, &decoding)?;
+        if !found.is_empty() {
+            conflicts.extend(found);
+            continue;
+        }
+        let raw = RawText::decode(&data, &decoding)?;
+        let text = apply(&path, &raw, &edits, config.byte_indexed)?;
+        patched.push((path, raw.text, text));
+    }
+
+    let result = match mode {
+        "text" => {
+            let texts = PyDict::new(py);
+            for (path, _, text) in &patched {
+                texts.set_item(&**path, text)?;
+            }
+            texts.into_any().unbind()
+        }
+        "write" => {
+            for (path, _, text) in &patched {
+                std::fs::write(path.as_ref(), decoding.encode(text)?)?;
+            }
+            let paths: Vec<&str> = patched.iter().map(|(path, _, _)| &**path).collect();
+            paths.into_py_any(py)?
+        }
+        _ => unified_diff(py, &patched)?.into_py_any(py)?,
+    };
+    Ok((result, conflicts))
+}
+
+/// Replace the spans of sorted, non-overlapping edits.
+fn apply(path: &str, raw: &RawText, edits: &[Edit], byte_indexed: bool) -> PyResult<String> {
+    let mut text = String::with_capacity(raw.text.len());
+    let mut last = 0;
+    for edit in edits {
+        let start = raw.offset(edit.span.start, byte_indexed);
+        let end = raw.offset(edit.span.end, byte_indexed);
+        let (Some(start), Some(end)) = (start, end) else {
+            let msg = format!(
+                "Edit {}..{} is out of bounds or inside a char in '{}'",
+                edit.span.start, edit.span.end, path
+            );
+            return Err(PyValueError::new_err(msg));
+        };
+        text.push_str(&raw.text[last..start]);
+        text.push_str(&edit.replacement);
+        last = end;
+    }
+    text.push_str(&raw.text[last..]);
+    Ok(text)
+}
+
+/// Create a report for each edit that overlaps a previous one.
+///
+/// Insertions at the same position conflict, since their order is ambiguous.
+fn find_conflicts(
+    path: &Arc<str>,
+    data: &[u8],
+    edits: &[Edit],
+    config: &Config,
+    decoding: &Decoding,
+) -> PyResult<Vec<Report>> {
+    let mut conflicts = Vec::new();
+    let mut previous: Option<&Edit> = None;
+    for edit in edits {
+        if let Some(prev) = previous {
+            let insertion = edit.span.is_empty() && edit.span == prev.span;
+            if edit.span.start < prev.span.end || insertion {
+                let source = Source::from_bytes(path.clone(), data, decoding)?;
+                let labels = vec![
+                    Label {
+                        message: Some("first edit".into()),
+                        ..Label::new(prev.span.clone())
+                    },
+                    Label {
+                        message: Some("conflicts with the first edit".into()),
+                        ..Label::new(edit.span.clone())
+                    },
+                ];
+                let report = Report::new(source, edit.span.clone(), config.clone()).set_params(
+                    None,
+                    Some("Conflicting edits".into()),
+                    ReportKind::Error,
+                    labels,
+                    Vec::new(),
+                    Vec::new(),
+                    Vec::new(),
+                );
+                conflicts.push(report);
+            }
+        }
+        if !matches!(previous, Some(prev) if edit.span.end < prev.span.end) {
+            previous = Some(edit);
+        }
+    }
+    Ok(conflicts)
+}
+
+fn unified_diff(py: Python<'_>, patched: &[(Arc<str>, String, String)]) -> PyResult<String> {
+    let difflib = PyModule::import(py, "difflib")?;
+    let mut diff = String::new();
+    for (path, old, new) in patched {
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("fromfile", format!("a/{}", path))?;
+        kwargs.set_item("tofile", format!("b/{}", path))?;
+        let old: Vec<&str> = old.split_inclusive('\n').collect();
+        let new: Vec<&str> = new.split_inclusive('\n').collect();
+        let lines = difflib.call_method("unified_diff", (old, new), Some(&kwargs))?;
+        for line in lines.try_iter()? {
+            diff.push_str(&line?.extract::<String>()?);
+        }
+    }
+    Ok(diff)
+}
@@ -0,0 +1,280 @@
+use crate::config::Config;
+use crate::label::Label;
+use crate::report::{Report, ReportKind, SubDiagnostic};
+use crate::report_error::ReportError;
+use crate::source::{Decoding, Source};
+use pyo3::exceptions::{PySyntaxError, PyValueError};
+use pyo3::prelude::*;
+use std::ops::Range;
+
+/// A sys.excepthook rendering tracebacks as reports.
+///
+/// Any failure while building the reports falls back to the hook that was
+/// installed before.
+#[pyclass(module = "theseus")]
+pub struct ExceptHook {
+    previous: PyObject,
+    config: Option<Config>,
+}
+
+impl ExceptHook {
+    fn config(&self, py: Python<'_>) -> PyResult<Config> {
+        if let Some(config) = &self.config {
+            return Ok(config.clone());
+        }
+        // Colors only make sense when stderr is a terminal
+        let stderr = PyModule::import(py, "sys")?.getattr("stderr")?;
+        let color = stderr.call_method0("isatty")?.is_truthy()?;
+        Ok(Config {
+            color,
+            ..Config::default()
+        })
+    }
+
+    /// Reports for the exception and the exceptions chained to it, starting
+    /// from the oldest one, each preceded by the standard separator message.
+    fn reports(
+        exc: &Bound<'_, PyAny>,
+        config: &Config,
+    ) -> PyResult<Vec<(Option<&'static str>, Report)>> {
+        let mut chain = Vec::new();
+        let mut seen = Vec::new();
+        let mut current = Some((None, exc.clone()));
+        while let Some((separator, exc)) = current.take() {
+            seen.push(exc.as_ptr());
+            let cause = exc.getattr("__cause__")?;
+            let context = exc.getattr("__context__")?;
+            let suppressed = exc.getattr("__suppress_context__")?.is_truthy()?;
+            current = if !cause.is_none() {
+                Some((
+                    Some("The above exception was the direct cause of the following exception:"),
+                    cause,
+                ))
+            } else if !context.is_none() && !suppressed {
+                Some((
+                    Some("During handling of the above exception, another exception occurred:"),
+                    context,
+                ))
+            } else {
+                None
+            };
+            if current
+                .as_ref()
+                .is_some_and(|(_, exc)| seen.contains(&exc.as_ptr()))
+            {
+                current = None;
+            }
+            chain.push((separator, exception_report(&exc, config)?));
+        }
+        // The separator goes before the exception that was raised later
+        let mut reports = Vec::new();
+        let mut separator = None;
+        for (next_separator, report) in chain.into_iter().rev() {
+            reports.push((separator, report));
+            separator = next_separator;
+        }
+        Ok(reports)
+    }
+
+    fn print(&self, exc: &Bound<'_, PyAny>) -> PyResult<()> {
+        let py = exc.py();
+        let config = self.config(py)?;
+        // Everything is rendered before writing, so a failure falls back to
+        // the previous hook without leaving half a traceback behind
+        let mut output = String::new();
+        if let Ok(error) = exc.downcast::<ReportError>() {
+            // Errors carrying reports only need the reports themselves
+            for report in error.borrow().reports.iter() {
+                let report = report.bind(py).borrow();
+                output += &match &self.config {
+                    Some(config) => report
+                        .with_config(py, config.clone())
+                        .render(config.color)?,
+                    None => report.render(report.config.color)?,
+                };
+            }
+        } else {
+            for (separator, report) in Self::reports(exc, &config)? {
+                if let Some(separator) = separator {
+                    output += &format!("\n{separator}\n\n");
+                }
+                output += &report.render(config.color)?;
+            }
+        }
+        let stderr = PyModule::import(py, "sys")?.getattr("stderr")?;
+        stderr.call_method1("write", (output,))?;
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl ExceptHook {
+    fn __call__(
+        &self,
+        exc_type: &Bound<'_, PyAny>,
+        exc: &Bound<'_, PyAny>,
+        traceback: &Bound<'_, PyAny>,
+    ) -> PyResult<()> {
+        if self.print(exc).is_err() {
+            self.previous
+                .bind(exc.py())
+                .call1((exc_type, exc, traceback))?;
+        }
+        Ok(())
+    }
+}
+
+/// Build a report for a single exception, with a label for each frame of
+/// its traceback that points to a real file.
+fn exception_report(exc: &Bound<'_, PyAny>, config: &Config) -> PyResult<Report> {
+    let py = exc.py();
+    let decoding = Decoding::default();
+    let message = exception_message(exc)?;
+    let notes: Vec<SubDiagnostic> = match exc.getattr("__notes__") {
+        Ok(notes) => notes
+            .extract::<Vec<String>>()?
+            .into_iter()
+            .map(SubDiagnostic::Text)
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    let traceback = PyModule::import(py, "traceback")?;
+    let frames = traceback.call_method1("extract_tb", (exc.getattr("__traceback__")?,))?;
+    let path_type = PyModule::import(py, "pathlib")?.getattr("Path")?;
+    let mut sources: Vec<Source> = Vec::new();
+    let mut labels = Vec::new();
+    for frame in frames.try_iter()? {
+        let frame = frame?;
+        let filename: String = frame.getattr("filename")?.extract()?;
+        let path = path_type.call1((filename,))?;
+        if !path.call_method0("is_file")?.is_truthy()? {
+            continue;
+        }
+        let path = path.str()?.to_string();
+        let idx = match sources.iter().position(|source| *source.path == *path) {
+            Some(idx) => idx,
+            None => {
+                sources.push(Source::from_python(&path_type.call1((&path,))?, &decoding)?);
+                sources.len() - 1
+            }
+        };
+        let Some(span) = frame_span(&frame, &sources[idx], config.byte_indexed)? else {
+            continue;
+        };
+        let name: String = frame.getattr("name")?.extract()?;
+        labels.push((path, span, name));
+    }
+
+    // SyntaxErrors point to the code that failed to parse rather than to
+    // the frame that tried to compile it
+    let mut report = if exc.is_instance_of::<PySyntaxError>() {
+        Report::from_exception(
+            exc,
+            None,
+            None,
+            None,
+            None,
+            config.clone(),
+            None,
+            "utf-8",
+            "strict",
+        )?
+    } else {
+        let Some((path, span, _)) = labels.last() else {
+            let msg = "No frame of the traceback points to a source file";
+            return Err(PyValueError::new_err(msg));
+        };
+        let main = sources
+            .iter()
+            .position(|source| *source.path == **path)
+            .unwrap_or_default();
+        let source = sources.remove(main);
+        let span = span.clone();
+        let kind = ReportKind::Error;
+        let (no_labels, helps) = (Vec::new(), Vec::new());
+        let files = std::mem::take(&mut sources);
+        let report = Report::new(source, span, config.clone());
+        report.set_params(None, Some(message), kind, no_labels, notes, helps, files)
+    };
+    let count = labels.len();
+    for (order, (path, span, name)) in labels.into_iter().enumerate() {
+        let innermost = order + 1 == count && !exc.is_instance_of::<PySyntaxError>();
+        let message = format!("in {name}");
+        let order = Some(order as i32);
+        let (path, message) = (Some(path.as_str()), Some(message.as_str()));
+        let label = Label::from_offsets(
+            span.start, span.end, path, message, None, order, None, innermost,
+        )?;
+        let path = label.target.clone();
+        report.add_frame(
+            label,
+            sources
+                .iter()
+                .find(|source| Some(&source.path) == path.as_ref()),
+        );
+    }
+    Ok(report)
+}
+
+/// The last line of the standard traceback, e.g. "KeyError: 'x'".
+fn exception_message(exc: &Bound<'_, PyAny>) -> PyResult<String> {
+    let exc_type = exc.get_type();
+    let name: String = exc_type.getattr("__qualname__")?.extract()?;
+    let module: String = exc_type.getattr("__module__")?.extract()?;
+    let name = match module.as_str() {
+        "builtins" | "__main__" => name,
+        _ => format!("{module}.{name}"),
+    };
+    let text = exc.str()?.to_string();
+    Ok(match text.is_empty() {
+        true => name,
+        false => format!("{name}: {text}"),
+    })
+}
+
+/// Span of the expression being evaluated in a frame.
+///
+/// Python 3.11+ knows the exact columns, in UTF-8 bytes. Otherwise the whole
+/// line is used, without its indentation.
+fn frame_span(
+    frame: &Bound<'_, PyAny>,
+    source: &Source,
+    byte_indexed: bool,
+) -> PyResult<Option<Range<usize>>> {
+    let Some(line) = frame.getattr("lineno")?.extract::<Option<usize>>()? else {
+        return Ok(None);
+    };
+    let attr = |name: &str| -> PyResult<Option<usize>> {
+        match frame.getattr(name) {
+            Ok(value) => value.extract(),
+            Err(_) => Ok(None),
+        }
+    };
+    let span = match (attr("colno")?, attr("end_lineno")?, attr("end_colno")?) {
+        (Some(col), Some(end_line), Some(end_col)) => {
+            source.line_col(line, col, true)..source.line_col(end_line, end_col, true)
+        }
+        _ => {
+            let start = source.line_col(line, 0, true);
+            let end = source.line_col(line + 1, 0, true);
+            let text = &source.text[start..end];
+            let indent = text.len() - text.trim_start().len();
+            start + indent..start + text.trim_end().len()
+        }
+    };
+    Ok(Some(source.original_span(
+        span.start..span.end.max(span.start),
+        byte_indexed,
+    )))
+}
+
+/// Install a sys.excepthook that renders uncaught exceptions as reports.
+#[pyfunction]
+#[pyo3(signature=(*, config=None))]
+pub fn install_excepthook(py: Python<'_>, config: Option<Config>) -> PyResult<()> {
+    let sys = PyModule::import(py, "sys")?;
+    let previous = sys.getattr("excepthook")?.unbind();
+    let hook = ExceptHook { previous, config };
+    sys.setattr("excepthook", Bound::new(py, hook)?)
+}
@@ -0,0 +1,80 @@
+use crate::label::Label;
+use pyo3::prelude::*;
+
+/// A frame of a macro expansion backtrace.
+#[pyclass(module = "theseus", frozen, eq, hash)]
+#[derive(Clone, Debug, PartialEq, Hash)]
+pub struct Expansion {
+    pub(crate) name: String,
+    pub(crate) call_site: Label,
+    pub(crate) definition: Option<Label>,
+}
+
+impl Expansion {
+    pub fn new(name: String, call_site: Label, definition: Option<Label>) -> Self {
+        Expansion {
+            name,
+            call_site,
+            definition,
+        }
+    }
+
+    pub fn call_site_message(&self) -> String {
+        match &self.call_site.message {
+            Some(msg) => msg.clone(),
+            None => format!("in this expansion of `{}`", self.name),
+        }
+    }
+
+    pub fn definition_message(&self) -> Option<String> {
+        let definition = self.definition.as_ref()?;
+        match &definition.message {
+            Some(msg) => Some(msg.clone()),
+            None => Some(format!("`{}` defined here", self.name)),
+        }
+    }
+}
+
+#[pymethods]
+impl Expansion {
+    #[new]
+    #[pyo3(signature=(name, call_site, definition=None))]
+    fn py_new(name: String, call_site: Label, definition: Option<Label>) -> Self {
+        Expansion::new(name, call_site, definition)
+    }
+
+    #[getter]
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[getter]
+    fn call_site(&self) -> Label {
+        self.call_site.clone()
+    }
+
+    #[getter]
+    fn definition(&self) -> Option<Label> {
+        self.definition.clone()
+    }
+
+    fn __getnewargs__(&self) -> (String, Label, Option<Label>) {
+        (
+            self.name.clone(),
+            self.call_site.clone(),
+            self.definition.clone(),
+        )
+    }
+
+    fn __repr__(&self) -> String {
+        let mut args = vec![format!("{:?}", self.name), self.call_site.__repr__()];
+        if let Some(definition) = &self.definition {
+            args.push(format!("definition={}", definition.__repr__()));
+        }
+        format!("Expansion({})", args.join(", "))
+    }
+
+    fn __str__(&self) -> String {
+        self.__repr__()
+    }
+}
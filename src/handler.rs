@@ -0,0 +1,123 @@
+use crate::config::Config;
+use crate::report::{Report, ReportKind};
+use crate::source::{Decoding, Source};
+use crate::span::SpanArg;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyTuple, PyType};
+
+/// Create the ReportHandler class.
+///
+/// logging.Handler is a Python class, so the subclass is created at runtime
+/// and its methods are bound with functools.partialmethod.
+pub fn report_handler_class<'py>(m: &Bound<'py, PyModule>) -> PyResult<Bound<'py, PyAny>> {
+    let py = m.py();
+    let base = PyModule::import(py, "logging")?.getattr("Handler")?;
+    let partialmethod = PyModule::import(py, "functools")?.getattr("partialmethod")?;
+    let namespace = PyDict::new(py);
+    namespace.set_item("__module__", "theseus")?;
+    namespace.set_item(
+        "__doc__",
+        "A logging handler rendering records with spans as reports.",
+    )?;
+    namespace.set_item(
+        "__init__",
+        partialmethod.call1((wrap_pyfunction!(handler_init, m)?,))?,
+    )?;
+    namespace.set_item(
+        "emit",
+        partialmethod.call1((wrap_pyfunction!(handler_emit, m)?,))?,
+    )?;
+    let bases = PyTuple::new(py, [base])?;
+    py.get_type::<PyType>()
+        .call1(("ReportHandler", bases, namespace))
+}
+
+#[pyfunction]
+#[pyo3(signature=(handler, level=None, *, stream=None, config=None))]
+fn handler_init(
+    handler: &Bound<'_, PyAny>,
+    level: Option<&Bound<'_, PyAny>>,
+    stream: Option<&Bound<'_, PyAny>>,
+    config: Option<Config>,
+) -> PyResult<()> {
+    let base = PyModule::import(handler.py(), "logging")?.getattr("Handler")?;
+    match level {
+        Some(level) => base.call_method1("__init__", (handler, level))?,
+        None => base.call_method1("__init__", (handler,))?,
+    };
+    handler.setattr("stream", stream)?;
+    handler.setattr("config", config)?;
+    Ok(())
+}
+
+#[pyfunction]
+fn handler_emit(handler: &Bound<'_, PyAny>, record: &Bound<'_, PyAny>) -> PyResult<()> {
+    // Like Handler.handleError(), which needs an exception being handled
+    if let Err(err) = emit(handler, record) {
+        let py = handler.py();
+        if PyModule::import(py, "logging")?
+            .getattr("raiseExceptions")?
+            .is_truthy()?
+        {
+            let stderr = PyModule::import(py, "sys")?.getattr("stderr")?;
+            stderr.call_method1("write", ("--- Logging error ---\n",))?;
+            err.display(py);
+        }
+    }
+    Ok(())
+}
+
+fn emit(handler: &Bound<'_, PyAny>, record: &Bound<'_, PyAny>) -> PyResult<()> {
+    let py = handler.py();
+    let stream = match handler.getattr("stream")? {
+        stream if stream.is_none() => PyModule::import(py, "sys")?.getattr("stderr")?,
+        stream => stream,
+    };
+    let config: Option<Config> = handler.getattr("config")?.extract()?;
+    let color = match &config {
+        Some(config) => config.color,
+        None => stream
+            .call_method0("isatty")
+            .and_then(|tty| tty.is_truthy())
+            .unwrap_or(false),
+    };
+    let kind = ReportKind::from_level(record.getattr("levelno")?.extract()?);
+
+    let text = if let Some(report) = attached_report(record)? {
+        report.borrow_mut().render_as(kind, color)?
+    } else if record.hasattr("source")? && record.hasattr("span")? {
+        let config = config.unwrap_or_else(|| Config {
+            color,
+            ..Config::default()
+        });
+        let source = Source::from_python(&record.getattr("source")?, &Decoding::default())?;
+        let span = SpanArg::from_object(&record.getattr("span")?)?;
+        let span = span.resolve(Some(&source), config.byte_indexed);
+        let message = record.call_method0("getMessage")?.extract()?;
+        let (labels, notes, helps, files) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        let report = Report::new(source, span, config);
+        let report = report.set_params(None, Some(message), kind, labels, notes, helps, files);
+        report.render(color)?
+    } else {
+        // Plain records are formatted as usual
+        let text: String = handler.call_method1("format", (record,))?.extract()?;
+        text + "\n"
+    };
+    stream.call_method1("write", (text,))?;
+    if stream.hasattr("flush")? {
+        stream.call_method0("flush")?;
+    }
+    Ok(())
+}
+
+/// A report given as extra={"report": ...} or as the message itself.
+fn attached_report<'py>(record: &Bound<'py, PyAny>) -> PyResult<Option<Bound<'py, Report>>> {
+    for attr in ["report", "msg"] {
+        if let Ok(value) = record.getattr(attr) {
+            if let Ok(report) = value.downcast_into::<Report>() {
+                return Ok(Some(report));
+            }
+        }
+    }
+    Ok(None)
+}
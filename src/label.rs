@@ -1,36 +1,57 @@
-use crate::{_Label, color::Color};
+use crate::source::{Decoding, Source};
+use crate::span::SpanArg;
+use crate::{_Label, _Span, color::Color};
+use pyo3::types::{PyDict, PyTuple};
 use pyo3::{exceptions::PyValueError, prelude::*};
 use std::{hash::Hash, ops::Range, sync::Arc};
 
-#[pyclass(frozen, eq, hash)]
+/// Leads the messages of primary labels, like the `^^^` underlines of rustc.
+pub const PRIMARY_MARKER: &str = "^";
+
+#[pyclass(module = "theseus", frozen, eq, hash)]
 #[derive(Clone, Debug, PartialEq, Hash)]
 pub struct Label {
     pub(crate) span: Range<usize>,
+    // Discontinuous labels point to several ranges that share one message
+    pub(crate) extra: Vec<Range<usize>>,
     pub(crate) target: Option<Arc<str>>,
     pub(crate) message: Option<String>,
     pub(crate) color: Option<Color>,
     pub(crate) order: Option<i32>,
     pub(crate) priority: Option<i32>,
+    pub(crate) primary: bool,
+    // Unit of a span taken from an object, converted once the source is known
+    pub(crate) bytes: Option<bool>,
 }
 
 impl Label {
     pub fn new(span: Range<usize>) -> Self {
         Label {
             span,
+            extra: Vec::new(),
             target: None,
             message: None,
             color: None,
             order: None,
             priority: None,
+            primary: false,
+            bytes: None,
         }
     }
 
-    pub fn to_ariadne(&self) -> _Label {
-        let target = self.target.clone().unwrap_or("<string>".into());
-        let mut label = ariadne::Label::new((target, self.span.clone()));
-        if let Some(message) = &self.message {
-            label = label.with_message(message);
+    /// Convert to an ariadne label pointing to an already resolved span.
+    pub fn to_ariadne(&self, span: _Span) -> _Label {
+        let label = self.to_ariadne_unlabeled(span);
+        match &self.message {
+            Some(message) => label.with_message(message),
+            None => label,
         }
+    }
+
+    /// Convert to an ariadne label without the message, e.g., for one of the
+    /// ranges of a discontinuous label.
+    pub fn to_ariadne_unlabeled(&self, span: _Span) -> _Label {
+        let mut label = ariadne::Label::new(span);
         if let Some(color) = &self.color {
             label = label.with_color(color.inner);
         }
@@ -43,19 +64,73 @@ impl Label {
         label
     }
 
-    pub fn to_ariadne_with_default(&self, path: Arc<str>) -> _Label {
-        if self.target.is_none() {
-            self.clone().replace_target(path).to_ariadne()
+    /// Convert to an ariadne label styled as primary or secondary.
+    ///
+    /// Primary labels without a color are painted with the color of the
+    /// report kind and take precedence when highlighting overlapping spans.
+    /// Secondary labels are drawn as they are.
+    pub fn to_ariadne_styled(&self, span: _Span, primary: bool, kind: ariadne::Color) -> _Label {
+        self.style(self.to_ariadne(span), primary, kind)
+    }
+
+    pub fn style(&self, mut label: _Label, primary: bool, kind: ariadne::Color) -> _Label {
+        if !primary {
+            return label;
+        }
+        if self.color.is_none() {
+            label = label.with_color(kind);
+        }
+        if self.priority.is_none() {
+            label = label.with_priority(1);
+        }
+        label
+    }
+
+    /// All ranges of the label, starting with the main span.
+    pub fn ranges(&self) -> impl Iterator<Item = &Range<usize>> {
+        std::iter::once(&self.span).chain(&self.extra)
+    }
+
+    /// The range that shows the message of a discontinuous label.
+    pub fn message_range(&self) -> &Range<usize> {
+        self.ranges().max_by_key(|range| range.start).unwrap()
+    }
+
+    /// Message drawn on one of the ranges of the label.
+    ///
+    /// Discontinuous labels show their message on the last range, and the
+    /// other ranges lead to it with an ellipsis. Insertion points lead their
+    /// message with a caret, so they are not mistaken for a label on the next
+    /// character, and are drawn even without a message. Marked labels lead
+    /// theirs with the primary marker, so they stand out even without colors.
+    pub fn range_message(&self, range: &Range<usize>, marked: bool, ascii: bool) -> Option<String> {
+        let message = if !std::ptr::eq(range, self.message_range()) {
+            let ellipsis = if ascii { "..." } else { "…" };
+            self.message.as_ref().map(|_| ellipsis.into())
+        } else if range.is_empty() {
+            let caret = if ascii { "^" } else { "‸" };
+            Some(lead(caret, self.message.as_deref()))
         } else {
-            self.to_ariadne()
+            self.message.clone()
+        };
+        match marked {
+            true => Some(lead(PRIMARY_MARKER, message.as_deref())),
+            false => message,
         }
     }
 
-    fn replace_target(mut self, target: Arc<str>) -> Self {
-        self.target = Some(target.clone());
-        self
+    /// Unit of the span, if it came from an object that knows it.
+    fn unit(&self) -> Option<&'static str> {
+        self.bytes
+            .map(|bytes| if bytes { "bytes" } else { "chars" })
     }
 
+    /// Zero-width labels point between two characters.
+    pub fn is_insertion(&self) -> bool {
+        self.span.is_empty()
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn set_params(
         mut self,
         target: Option<&str>,
@@ -63,6 +138,7 @@ impl Label {
         color: Option<Color>,
         order: Option<i32>,
         priority: Option<i32>,
+        primary: Option<bool>,
     ) -> Self {
         if let Some(target) = target {
             self.target = Some(target.into());
@@ -79,15 +155,16 @@ impl Label {
         if let Some(priority) = priority {
             self.priority = Some(priority);
         }
+        if let Some(primary) = primary {
+            self.primary = primary;
+        }
         self
     }
 }
 
-#[pymethods]
 impl Label {
-    #[new]
-    #[pyo3(signature=(start, end, *, path=None, message=None, color=None, order=None, priority=None))]
-    pub(crate) fn py_new(
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn from_offsets(
         start: usize,
         end: usize,
         path: Option<&str>,
@@ -95,27 +172,181 @@ impl Label {
         color: Option<Color>,
         order: Option<i32>,
         priority: Option<i32>,
+        primary: bool,
     ) -> PyResult<Self> {
         if start > end {
             let msg = "Start index must be less than or equal to end index";
             return Err(PyValueError::new_err(msg));
         }
-        Ok(Label::new(start..end).set_params(path, message, color, order, priority))
+        let label = Label::new(start..end);
+        Ok(label.set_params(path, message, color, order, priority, Some(primary)))
     }
-    #[pyo3(signature=(*, message=None, color=None, order=None, priority=None))]
+}
+
+#[pymethods]
+impl Label {
+    #[new]
+    #[pyo3(signature=(start, end=None, *, path=None, message=None, color=None, order=None, priority=None, primary=false))]
+    #[allow(clippy::too_many_arguments)]
+    fn py_new(
+        start: &Bound<'_, PyAny>,
+        end: Option<usize>,
+        path: Option<&str>,
+        message: Option<&str>,
+        color: Option<Color>,
+        order: Option<i32>,
+        priority: Option<i32>,
+        primary: bool,
+    ) -> PyResult<Self> {
+        // Labels are not bound to a source, so units are resolved by the report
+        let span = SpanArg::from_params(start, end)?;
+        let (range, bytes) = (span.range, span.bytes);
+        let label = Label::from_offsets(
+            range.start,
+            range.end,
+            path,
+            message,
+            color,
+            order,
+            priority,
+            primary,
+        )?;
+        Ok(Label { bytes, ..label })
+    }
+    #[staticmethod]
+    #[pyo3(signature=(offset, *, path=None, message=None, color=None, order=None, priority=None, primary=false))]
+    #[allow(clippy::too_many_arguments)]
+    fn insertion(
+        offset: usize,
+        path: Option<&str>,
+        message: Option<&str>,
+        color: Option<Color>,
+        order: Option<i32>,
+        priority: Option<i32>,
+        primary: bool,
+    ) -> Self {
+        let label = Label::new(offset..offset);
+        label.set_params(path, message, color, order, priority, Some(primary))
+    }
+
+    #[staticmethod]
+    #[pyo3(signature=(ranges, *, path=None, message=None, color=None, order=None, priority=None, primary=false))]
+    #[allow(clippy::too_many_arguments)]
+    fn multi(
+        ranges: Vec<(usize, usize)>,
+        path: Option<&str>,
+        message: Option<&str>,
+        color: Option<Color>,
+        order: Option<i32>,
+        priority: Option<i32>,
+        primary: bool,
+    ) -> PyResult<Self> {
+        if ranges.iter().any(|(start, end)| start > end) {
+            let msg = "Start index must be less than or equal to end index";
+            return Err(PyValueError::new_err(msg));
+        }
+        let mut ranges = ranges.into_iter().map(|(start, end)| start..end);
+        let Some(span) = ranges.next() else {
+            let msg = "A label must have at least one range";
+            return Err(PyValueError::new_err(msg));
+        };
+        let label = Label {
+            extra: ranges.collect(),
+            ..Label::new(span)
+        };
+        Ok(label.set_params(path, message, color, order, priority, Some(primary)))
+    }
+
+    #[staticmethod]
+    #[pyo3(signature=(node, source, *, byte_indexed=false, path=None, message=None, color=None, order=None, priority=None, primary=false))]
+    #[allow(clippy::too_many_arguments)]
+    fn from_ast(
+        node: &Bound<'_, PyAny>,
+        source: &Bound<'_, PyAny>,
+        byte_indexed: bool,
+        path: Option<&str>,
+        message: Option<&str>,
+        color: Option<Color>,
+        order: Option<i32>,
+        priority: Option<i32>,
+        primary: bool,
+    ) -> PyResult<Self> {
+        let source = Source::from_python(source, &Decoding::default())?;
+        let label = Label::new(source.node_span(node, byte_indexed)?);
+        Ok(label.set_params(path, message, color, order, priority, Some(primary)))
+    }
+
+    #[getter]
+    #[pyo3(name = "ranges")]
+    fn py_ranges(&self) -> Vec<(usize, usize)> {
+        self.ranges()
+            .map(|range| (range.start, range.end))
+            .collect()
+    }
+
+    #[getter]
+    #[pyo3(name = "is_insertion")]
+    fn py_is_insertion(&self) -> bool {
+        self.is_insertion()
+    }
+
+    #[pyo3(signature=(*, message=None, color=None, order=None, priority=None, primary=None))]
     fn copy(
         &self,
         message: Option<&str>,
         color: Option<Color>,
         order: Option<i32>,
         priority: Option<i32>,
+        primary: Option<bool>,
     ) -> Self {
         self.clone()
-            .set_params(None, message, color, order, priority)
+            .set_params(None, message, color, order, priority, primary)
+    }
+
+    #[getter]
+    fn primary(&self) -> bool {
+        self.primary
     }
 
-    fn __repr__(&self) -> String {
-        let mut args = vec![self.span.start.to_string(), self.span.end.to_string()];
+    /// Labels are rebuilt with `Label.multi`, which also covers labels with
+    /// a single range, or from a span tuple if they have a unit.
+    fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<(Bound<'py, PyAny>, Bound<'py, PyTuple>)> {
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("path", self.target.as_deref())?;
+        kwargs.set_item("message", self.message.as_deref())?;
+        kwargs.set_item("color", self.color)?;
+        kwargs.set_item("order", self.order)?;
+        kwargs.set_item("priority", self.priority)?;
+        kwargs.set_item("primary", self.primary)?;
+        let partial = PyModule::import(py, "functools")?.getattr("partial")?;
+        let cls = py.get_type::<Label>();
+        if let Some(unit) = self.unit() {
+            let constructor = partial.call((cls,), Some(&kwargs))?;
+            let span = (self.span.start, self.span.end, unit).into_pyobject(py)?;
+            return Ok((constructor, PyTuple::new(py, [span])?));
+        }
+        let constructor = partial.call((cls.getattr("multi")?,), Some(&kwargs))?;
+        Ok((constructor, PyTuple::new(py, [self.py_ranges()])?))
+    }
+
+    pub(crate) fn __repr__(&self) -> String {
+        let mut args = match (self.extra.is_empty(), self.unit()) {
+            (true, Some(unit)) => vec![format!(
+                "({}, {}, {unit:?})",
+                self.span.start, self.span.end
+            )],
+            (true, None) => vec![self.span.start.to_string(), self.span.end.to_string()],
+            (false, _) => {
+                let ranges: Vec<String> = self
+                    .ranges()
+                    .map(|range| format!("({}, {})", range.start, range.end))
+                    .collect();
+                vec![format!("[{}]", ranges.join(", "))]
+            }
+        };
         if let Some(target) = &self.target {
             args.push(format!("path={target:?}"));
         }
@@ -131,11 +362,25 @@ impl Label {
         if let Some(priority) = self.priority {
             args.push(format!("priority={priority}"));
         }
+        if self.primary {
+            args.push("primary=True".into());
+        }
         let args = args.join(", ");
-        format!("Label({args})")
+        match self.extra.is_empty() {
+            true => format!("Label({args})"),
+            false => format!("Label.multi({args})"),
+        }
     }
 
     fn __str__(&self) -> String {
         self.__repr__()
     }
 }
+
+/// Lead a message with a marker, or show just the marker.
+fn lead(marker: &str, message: Option<&str>) -> String {
+    match message {
+        Some(message) => format!("{marker} {message}"),
+        None => marker.into(),
+    }
+}
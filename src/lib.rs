@@ -11,6 +11,23 @@ mod label;
 use label::Label;
 mod config;
 use config::Config;
+mod cache;
+mod source;
+use source::Source;
+mod source_map;
+use source_map::SourceMap;
+mod expansion;
+use expansion::Expansion;
+mod suggestion;
+use suggestion::Suggestion;
+mod converters;
+mod edits;
+mod excepthook;
+mod handler;
+mod report_error;
+mod warnings;
+use report_error::ReportError;
+mod span;
 
 // Rust type definitions
 pub(crate) type _Span = (Arc<str>, Range<usize>);
@@ -24,5 +41,20 @@ fn theseus(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<Report>()?;
     m.add_class::<Label>()?;
     m.add_class::<Config>()?;
+    m.add_class::<Source>()?;
+    m.add_class::<SourceMap>()?;
+    m.add_class::<Expansion>()?;
+    m.add_class::<Suggestion>()?;
+    m.add_class::<ReportError>()?;
+    m.add_function(wrap_pyfunction!(edits::apply_edits, m)?)?;
+    m.add_function(wrap_pyfunction!(span::register_span_adapter, m)?)?;
+    m.add_function(wrap_pyfunction!(excepthook::install_excepthook, m)?)?;
+    m.add("ReportHandler", handler::report_handler_class(m)?)?;
+    m.add_function(wrap_pyfunction!(warnings::warn, m)?)?;
+    m.add_function(wrap_pyfunction!(warnings::showwarning, m)?)?;
+    m.add_function(wrap_pyfunction!(
+        converters::register_exception_converter,
+        m
+    )?)?;
     Ok(())
 }
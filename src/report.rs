@@ -2,27 +2,37 @@ use std::io::Write;
 use std::ops::Range;
 use std::sync::Arc;
 
-use crate::_Report;
+use crate::cache::SourceCache;
 use crate::color::Color;
+use crate::color_generator::ColorSequence;
 use crate::config::Config;
-use crate::label::Label;
-use pyo3::exceptions::{PyFileNotFoundError, PyTypeError, PyUnicodeDecodeError, PyValueError};
-use pyo3::types::{PyDict, PyIterator, PyList, PyString};
+use crate::converters;
+use crate::expansion::Expansion;
+use crate::label::{Label, PRIMARY_MARKER};
+use crate::source::{char_to_byte, floor_char_boundary, from_path_or_str, Decoding, Source};
+use crate::span::SpanArg;
+use crate::suggestion::Suggestion;
+use crate::{_Report, _Span};
+use pyo3::exceptions::{PyKeyError, PyTypeError, PyUnicodeDecodeError, PyValueError};
+use pyo3::types::{PyDict, PyIterator, PyList, PyString, PyTuple, PyType};
 use pyo3::{prelude::*, IntoPyObjectExt};
 
-#[pyclass]
+#[pyclass(module = "theseus")]
 pub struct Report {
     source: Source,
     span: Range<usize>,
-    config: Config,
+    pub(crate) config: Config,
     code: Option<String>,
     message: Option<String>,
     kind: ReportKind,
     labels: Vec<Label>,
-    notes: Vec<String>,
-    helps: Vec<String>,
+    notes: Vec<SubDiagnostic>,
+    helps: Vec<SubDiagnostic>,
     files: Vec<Source>,
-    colors: ariadne::ColorGenerator,
+    includes: Vec<Label>,
+    expansions: Vec<Expansion>,
+    suggestions: Vec<Suggestion>,
+    colors: ColorSequence,
 }
 
 impl Report {
@@ -38,16 +48,22 @@ impl Report {
             notes: Vec::new(),
             helps: Vec::new(),
             files: Vec::new(),
-            colors: ariadne::ColorGenerator::new(),
+            includes: Vec::new(),
+            expansions: Vec::new(),
+            suggestions: Vec::new(),
+            colors: ColorSequence::new(),
         }
     }
 
-    pub fn build_ariadne_report(&self) -> _Report<'_> {
-        let path = self.source.path.clone();
-        let mut builder =
-            ariadne::Report::build(self.kind.to_ariadne(), (path.clone(), self.span.clone()));
+    pub fn build_ariadne_report(&self, cache: &SourceCache, color: bool) -> _Report<'_> {
+        let mut generated = Vec::new();
+        let (span, snippet) = self.locate(None, self.span.clone());
+        let span = cache.insertion_point(span, self.config.byte_indexed);
+        generated.extend(snippet);
+        let mut builder = ariadne::Report::build(self.kind.to_ariadne(), span.clone());
+        let kind_color = self.kind.color();
 
-        builder = builder.with_config(self.config.inner);
+        builder = builder.with_config(self.config.inner().with_color(color));
         if let Some(code) = self.code.as_ref() {
             builder = builder.with_code(code);
         }
@@ -55,15 +71,534 @@ impl Report {
             builder = builder.with_message(msg);
         }
 
-        builder.with_notes(self.notes.clone());
-        builder.with_helps(self.helps.clone());
-        for label in &self.labels {
-            builder = builder.with_label(label.to_ariadne_with_default(path.clone()));
+        builder.with_notes(self.notes.iter().filter_map(SubDiagnostic::text));
+        builder.with_helps(self.helps.iter().filter_map(SubDiagnostic::text));
+        // Labels on the report span are primary unless others are marked so,
+        // and reports without labels point to their own span
+        let has_primary = self.labels.iter().any(|label| label.primary);
+        let on_report_span = |label: &Label| {
+            self.resolve(label.target.as_ref(), label.bytes, &label.span) == self.span
+                && label
+                    .target
+                    .iter()
+                    .all(|target| *target == self.source.path)
+        };
+        if self.labels.is_empty() {
+            let label = ariadne::Label::new(span.clone()).with_message(PRIMARY_MARKER);
+            builder = builder.with_label(label.with_color(kind_color).with_priority(1));
+        }
+        let primaries: Vec<bool> = self
+            .labels
+            .iter()
+            .map(|label| label.primary || (!has_primary && on_report_span(label)))
+            .collect();
+        // Primary labels only need a marker to tell them from secondary ones
+        let mark_primary = primaries.contains(&false);
+        for (label, &primary) in self.labels.iter().zip(&primaries) {
+            for range in label.ranges() {
+                let (span, snippet) = self.locate_label(label, range, cache);
+                generated.extend(snippet);
+                let mut part = label.to_ariadne_unlabeled(span);
+                let marked = primary && mark_primary;
+                if let Some(message) = label.range_message(range, marked, self.config.ascii) {
+                    part = part.with_message(message);
+                }
+                builder = builder.with_label(label.style(part, primary, kind_color));
+            }
+        }
+        // Include frames go after the labels, so the header shows the error
+        if !self.config.compact {
+            for label in &self.includes {
+                let (span, _) = self.locate_label(label, &label.span, cache);
+                let mut include = label.to_ariadne_styled(span, false, kind_color);
+                if label.message.is_none() {
+                    include = include.with_message("included from here");
+                }
+                builder = builder.with_label(include);
+            }
+        }
+        self.add_expansions(&mut builder, cache);
+        if self.config.show_generated {
+            let mut shown = Vec::new();
+            for snippet in generated {
+                if !shown.contains(&snippet) {
+                    builder.add_note(format!("in generated code: {}", snippet));
+                    shown.push(snippet);
+                }
+            }
         }
 
         builder.finish()
     }
 
+    /// Render the report to a string, e.g., to show it in an exception.
+    pub fn render(&self, color: bool) -> PyResult<String> {
+        let mut buffer = Vec::new();
+        self.write(&mut buffer, false, Some(color))?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+
+    /// Copy the report. Child reports are shared, not copied.
+    pub fn clone_ref(&self, py: Python<'_>) -> Self {
+        let clone_all =
+            |subs: &Vec<SubDiagnostic>| subs.iter().map(|sub| sub.clone_ref(py)).collect();
+        Report {
+            source: self.source.clone(),
+            span: self.span.clone(),
+            config: self.config.clone(),
+            code: self.code.clone(),
+            message: self.message.clone(),
+            kind: self.kind.clone(),
+            labels: self.labels.clone(),
+            notes: clone_all(&self.notes),
+            helps: clone_all(&self.helps),
+            files: self.files.clone(),
+            includes: self.includes.clone(),
+            expansions: self.expansions.clone(),
+            suggestions: self.suggestions.clone(),
+            colors: self.colors.clone(),
+        }
+    }
+
+    /// Copy the report with another configuration, e.g., the one of a hook.
+    pub fn with_config(&self, py: Python<'_>, config: Config) -> Self {
+        Report {
+            config,
+            ..self.clone_ref(py)
+        }
+    }
+
+    /// Replace the notes or the helps, refusing the report itself.
+    fn replace_sub_diagnostics(
+        mut slf: PyRefMut<'_, Self>,
+        subs: Vec<SubDiagnostic>,
+        helps: bool,
+    ) -> PyResult<()> {
+        for child in subs.iter().filter_map(SubDiagnostic::report) {
+            check_not_self(slf.as_ptr(), child.bind(slf.py()))?;
+        }
+        match helps {
+            true => slf.helps = subs,
+            false => slf.notes = subs,
+        }
+        Ok(())
+    }
+
+    /// Apply the keyword arguments of copy() or update(). The report will be
+    /// stored in `this`, which cannot become one of its sub-diagnostics.
+    fn apply_changes(
+        &mut self,
+        this: &Bound<'_, PyAny>,
+        changes: &Bound<'_, PyDict>,
+        method: &str,
+    ) -> PyResult<()> {
+        let (mut kind, mut color) = (None, None);
+        for (key, value) in changes.iter() {
+            match key.extract::<&str>()? {
+                "code" => self.code = value.extract()?,
+                "message" => self.message = value.extract()?,
+                "kind" => kind = Some(value.extract::<String>()?),
+                "color" => color = Some(value.extract::<Color>()?),
+                "span" => self.set_span(value.extract()?)?,
+                "labels" => self.labels = value.extract()?,
+                "notes" => self.notes = value.extract()?,
+                "helps" => self.helps = value.extract()?,
+                "config" => self.config = value.extract()?,
+                key => {
+                    let msg = format!("{method}() got an unexpected keyword argument '{key}'");
+                    return Err(PyTypeError::new_err(msg));
+                }
+            }
+        }
+        if kind.is_some() || color.is_some() {
+            self.kind = ReportKind::from_params(kind.as_deref(), color)?;
+        }
+        for child in self
+            .notes
+            .iter()
+            .chain(&self.helps)
+            .filter_map(SubDiagnostic::report)
+        {
+            check_not_self(this.as_ptr(), child.bind(this.py()))?;
+        }
+        Ok(())
+    }
+
+    /// Compare the contents of two reports, ignoring the generated colors.
+    fn equals(&self, py: Python<'_>, other: &Report) -> PyResult<bool> {
+        let same_sources = |a: &[Source], b: &[Source]| {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.pair() == b.pair())
+        };
+        Ok(self.source.pair() == other.source.pair()
+            && self.span == other.span
+            && self.config == other.config
+            && self.code == other.code
+            && self.message == other.message
+            && self.kind == other.kind
+            && self.labels == other.labels
+            && same_sources(&self.files, &other.files)
+            && self.includes == other.includes
+            && self.expansions == other.expansions
+            && self.suggestions == other.suggestions
+            && SubDiagnostic::all_equal(py, &self.notes, &other.notes)?
+            && SubDiagnostic::all_equal(py, &self.helps, &other.helps)?)
+    }
+
+    /// Path and 1-based line where the report starts, as in its header.
+    pub fn start_line(&self) -> PyResult<(Arc<str>, usize)> {
+        let cache = self.prepare_files()?;
+        let (span, _) = self.locate(None, self.span.clone());
+        let (path, range) = cache.insertion_point(span, self.config.byte_indexed);
+        let text = cache.text(&path).unwrap_or_default();
+        let before = match self.config.byte_indexed {
+            true => &text[..floor_char_boundary(text, range.start.min(text.len()))],
+            false => &text[..char_to_byte(text, range.start)],
+        };
+        Ok((path.clone(), before.matches('\n').count() + 1))
+    }
+
+    /// A one line summary of the report: its message, or its code.
+    pub fn summary(&self) -> String {
+        match (&self.message, &self.code) {
+            (Some(message), _) => message.clone(),
+            (None, Some(code)) => code.clone(),
+            (None, None) => String::new(),
+        }
+    }
+
+    /// Render the report as another kind, e.g., the kind matching the level
+    /// of a log record.
+    pub fn render_as(&mut self, kind: ReportKind, color: bool) -> PyResult<String> {
+        let kind = std::mem::replace(&mut self.kind, kind);
+        let result = self.render(color);
+        self.kind = kind;
+        result
+    }
+
+    /// Write the report, preceded by the include stack in compact mode.
+    ///
+    /// The color setting of the config can be overridden for the report and
+    /// its children.
+    fn write(&self, w: &mut dyn Write, stdout: bool, color_override: Option<bool>) -> PyResult<()> {
+        let color = color_override.unwrap_or(self.config.color);
+        let mut cache = self.prepare_files()?;
+        if self.config.compact {
+            for label in &self.includes {
+                let (span, _) = self.locate_label(label, &label.span, &cache);
+                let location = cache.location(&span, self.config.byte_indexed);
+                match &label.message {
+                    Some(msg) => writeln!(w, "included from {}: {}", location, msg)?,
+                    None => writeln!(w, "included from {}", location)?,
+                }
+            }
+        }
+
+        let report = self.build_ariadne_report(&cache, color);
+        let mut buffer = Vec::new();
+        if stdout {
+            report.write_for_stdout(&mut cache, &mut buffer)?;
+        } else {
+            report.write(&mut cache, &mut buffer)?;
+        }
+        if !color && self.kind.is_custom() {
+            // ariadne always paints the header of custom kinds
+            buffer = strip_header_colors(&buffer);
+        }
+        w.write_all(&buffer)?;
+        for suggestion in &self.suggestions {
+            let (span, _) = self.locate(suggestion.target.as_ref(), suggestion.span.clone());
+            let config = Config {
+                color,
+                ..self.config.clone()
+            };
+            suggestion.write(w, &span, &cache, &config)?;
+        }
+
+        // Sub-diagnostics with snippets of their own are rendered right below
+        Python::with_gil(|py| {
+            let children = self.notes.iter().chain(&self.helps);
+            for child in children.filter_map(SubDiagnostic::report) {
+                child
+                    .bind(py)
+                    .try_borrow()?
+                    .write(w, stdout, color_override)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Build a sub-diagnostic from a string or a child report.
+    ///
+    /// Strings with a span or labels become child reports pointing to the
+    /// same files as this report. Labels without a path refer to the file of
+    /// the sub-diagnostic span.
+    #[allow(clippy::too_many_arguments)]
+    fn sub_diagnostic(
+        &self,
+        kind: ReportKind,
+        note: &Bound<'_, PyAny>,
+        start: Option<usize>,
+        end: Option<usize>,
+        path: Option<&Bound<'_, PyAny>>,
+        labels: Vec<Label>,
+    ) -> PyResult<SubDiagnostic> {
+        let has_span = start.is_some() || end.is_some() || path.is_some();
+        if let Ok(child) = note.downcast::<Report>() {
+            if has_span || !labels.is_empty() {
+                let msg = "Spans and labels cannot be attached to a child report";
+                return Err(PyValueError::new_err(msg));
+            }
+            return Ok(SubDiagnostic::Report(child.clone().unbind()));
+        }
+
+        let message: String = note.extract().map_err(|_| {
+            let msg = "Notes and helps must be strings or Report instances";
+            PyTypeError::new_err(msg)
+        })?;
+        if !has_span && labels.is_empty() {
+            return Ok(SubDiagnostic::Text(message));
+        }
+
+        let target = match path {
+            Some(path) => Some(from_path_or_str(path)?),
+            None => None,
+        };
+        let (target, span, labels) = match (start, end, labels.first()) {
+            (Some(start), Some(end), _) => {
+                if start > end {
+                    let msg = "Start index must be less than or equal to end index";
+                    return Err(PyValueError::new_err(msg));
+                }
+                let labels = match labels.is_empty() {
+                    true => vec![Label::new(start..end)],
+                    false => labels,
+                };
+                (target, start..end, labels)
+            }
+            (None, None, Some(first)) => {
+                let target = target.or(first.target.clone());
+                let span = self.resolve(target.as_ref(), first.bytes, &first.span);
+                (target, span, labels)
+            }
+            _ => {
+                let msg = "Both start and end must be given";
+                return Err(PyValueError::new_err(msg));
+            }
+        };
+        let Some(source) = self.find_source(target.as_ref()) else {
+            let msg = format!("Unknown path: {}", target.unwrap());
+            return Err(PyValueError::new_err(msg));
+        };
+
+        let files = std::iter::once(&self.source).chain(&self.files).cloned();
+        let child = Report::new(source.clone(), span, self.config.clone()).set_params(
+            None,
+            Some(message),
+            kind,
+            labels,
+            Vec::new(),
+            Vec::new(),
+            files.collect(),
+        );
+        Ok(SubDiagnostic::Report(Py::new(note.py(), child)?))
+    }
+
+    /// Add the macro expansion backtrace as secondary labels, or as notes in
+    /// compact mode.
+    fn add_expansions(&self, builder: &mut ariadne::ReportBuilder<'_, _Span>, cache: &SourceCache) {
+        let limit = self.config.expansion_limit.unwrap_or(usize::MAX);
+        for expansion in self.expansions.iter().take(limit) {
+            let call_site = &expansion.call_site;
+            let (span, _) = self.locate_label(call_site, &call_site.span, cache);
+            if self.config.compact {
+                let location = cache.location(&span, self.config.byte_indexed);
+                let msg = format!("in expansion of `{}` at {}", expansion.name, location);
+                builder.add_note(msg);
+                continue;
+            }
+
+            let label = call_site.to_ariadne_styled(span, false, self.kind.color());
+            builder.add_label(label.with_message(expansion.call_site_message()));
+            if let (Some(definition), Some(msg)) =
+                (&expansion.definition, expansion.definition_message())
+            {
+                let (span, _) = self.locate_label(definition, &definition.span, cache);
+                let label = definition.to_ariadne_styled(span, false, self.kind.color());
+                builder.add_label(label.with_message(msg));
+            }
+        }
+        if self.expansions.len() > limit {
+            let hidden = self.expansions.len() - limit;
+            let plural = if hidden == 1 { "" } else { "s" };
+            builder.add_note(format!(
+                "{} more macro expansion{} not shown",
+                hidden, plural
+            ));
+        }
+    }
+
+    pub fn with_includes(mut self, includes: Vec<Label>, decoding: &Decoding) -> PyResult<Self> {
+        for label in includes {
+            self.push_include(label, decoding)?;
+        }
+        Ok(self)
+    }
+
+    /// Add an include frame, loading its file if the report does not know it.
+    fn push_include(&mut self, label: Label, decoding: &Decoding) -> PyResult<()> {
+        let Some(path) = label.target.clone() else {
+            let msg = "Include frames must have a path";
+            return Err(PyValueError::new_err(msg));
+        };
+        if self.find_source(Some(&path)).is_none() {
+            self.files.push(Source::from_file(path, decoding)?);
+        }
+        self.includes.push(label);
+        Ok(())
+    }
+
+    /// Find the source for the given path and translate the span into its
+    /// normalized text.
+    ///
+    /// Spans in generated code are moved to the original file, if it is
+    /// available, and the generated snippet is returned along with the span.
+    /// Spans pointing to unknown paths are passed to ariadne unchanged.
+    fn locate(&self, target: Option<&Arc<str>>, span: Range<usize>) -> (_Span, Option<String>) {
+        let byte_indexed = self.config.byte_indexed;
+        let Some(source) = self.find_source(target) else {
+            return ((target.unwrap().clone(), span), None);
+        };
+        if let Some((path, original)) = source.map_span(&span) {
+            if let Some(original_source) = self.find_source(Some(&path)) {
+                let snippet = source.snippet(span, byte_indexed);
+                return (
+                    original_source.locate(original, byte_indexed),
+                    Some(snippet),
+                );
+            }
+        }
+        (source.locate(span, byte_indexed), None)
+    }
+
+    /// Locate a range of a label, which points to the main source if it has
+    /// no path.
+    fn locate_label(
+        &self,
+        label: &Label,
+        range: &Range<usize>,
+        cache: &SourceCache,
+    ) -> (_Span, Option<String>) {
+        let target = label.target.as_ref().unwrap_or(&self.source.path);
+        let range = self.resolve(Some(target), label.bytes, range);
+        let (span, snippet) = self.locate(Some(target), range);
+        (
+            cache.insertion_point(span, self.config.byte_indexed),
+            snippet,
+        )
+    }
+
+    /// Convert a range of a label to the units of the report, if the label
+    /// knows the units of its offsets.
+    fn resolve(
+        &self,
+        target: Option<&Arc<str>>,
+        bytes: Option<bool>,
+        range: &Range<usize>,
+    ) -> Range<usize> {
+        let span = SpanArg {
+            range: range.clone(),
+            bytes,
+        };
+        span.resolve(self.find_source(target), self.config.byte_indexed)
+    }
+
+    /// Find where a label should point to, given a match in the normalized
+    /// text of a source.
+    ///
+    /// The search is restricted to the given lines and returns the start and
+    /// end of the match in the original input.
+    fn search<F>(
+        &self,
+        path: Option<&str>,
+        lines: Option<(usize, usize)>,
+        find: F,
+    ) -> PyResult<(usize, usize)>
+    where
+        F: FnOnce(&str, &str) -> PyResult<Range<usize>>,
+    {
+        let target: Option<Arc<str>> = path.map(Arc::from);
+        let Some(source) = self.find_source(target.as_ref()) else {
+            let msg = format!("Unknown path: {}", path.unwrap_or_default());
+            return Err(PyValueError::new_err(msg));
+        };
+        let range = match lines {
+            Some((first, last)) => source.line_range(first, last)?,
+            None => 0..source.text.len(),
+        };
+        let found = find(&source.text[range.clone()], &source.path)?;
+        let found = range.start + found.start..range.start + found.end;
+        let span = source.original_span(found, self.config.byte_indexed);
+        Ok((span.start, span.end))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn label(
+        &mut self,
+        start: usize,
+        end: usize,
+        path: Option<&str>,
+        message: Option<&str>,
+        color: Option<Color>,
+        order: Option<i32>,
+        priority: Option<i32>,
+        primary: bool,
+    ) -> PyResult<Label> {
+        // Primary labels use the color of the report kind
+        let color = match primary {
+            true => color,
+            false => color.or_else(|| Some(Color::new(self.colors.next()))),
+        };
+        let label =
+            Label::from_offsets(start, end, path, message, color, order, priority, primary)?;
+        self.labels.push(label.clone());
+        Ok(label)
+    }
+
+    /// Add a label, together with its file if the report does not know it.
+    pub fn add_frame(&mut self, label: Label, source: Option<&Source>) {
+        if let Some(source) = source {
+            if self.find_source(Some(&source.path)).is_none() {
+                self.files.push(source.clone());
+            }
+        }
+        self.labels.push(label);
+    }
+
+    fn find_source(&self, target: Option<&Arc<str>>) -> Option<&Source> {
+        match target {
+            None => Some(&self.source),
+            Some(target) => std::iter::once(&self.source)
+                .chain(&self.files)
+                .rev()
+                .find_map(|source| source.find(target)),
+        }
+    }
+
+    /// Load the original files referenced by source maps that were not
+    /// passed explicitly, decoded like the other files of the report.
+    fn load_mapped_files(&mut self, decoding: &Decoding) -> PyResult<()> {
+        let paths: Vec<Arc<str>> = std::iter::once(&self.source)
+            .chain(&self.files)
+            .flat_map(Source::mapped_paths)
+            .collect();
+        for path in paths {
+            if self.find_source(Some(&path)).is_none() {
+                self.files.push(Source::from_file(path, decoding)?);
+            }
+        }
+        Ok(())
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn set_params(
         mut self,
@@ -71,8 +606,8 @@ impl Report {
         message: Option<String>,
         kind: ReportKind,
         labels: Vec<Label>,
-        notes: Vec<String>,
-        helps: Vec<String>,
+        notes: Vec<SubDiagnostic>,
+        helps: Vec<SubDiagnostic>,
         files: Vec<Source>,
     ) -> Self {
         self.code = code;
@@ -84,57 +619,131 @@ impl Report {
         self.files = files;
         self
     }
-    pub fn prepare_files(&self) -> Vec<(Arc<str>, Arc<str>)> {
-        let target = self.source.pair();
-        let mut files = vec![target];
-        for source in &self.files {
-            files.push(source.pair());
-        }
-        files
+    pub fn prepare_files(&self) -> PyResult<SourceCache> {
+        Python::with_gil(|py| {
+            let mut cache = SourceCache::new();
+            let sources = std::iter::once(&self.source).chain(&self.files);
+            for source in sources.flat_map(Source::parts) {
+                let (path, text) = source.pair();
+                let display = source.display_name(py, &self.config)?;
+                cache.insert(path, text, display);
+            }
+            Ok(cache)
+        })
     }
 }
 
 #[pymethods]
 impl Report {
     #[new]
-    #[pyo3(signature=(source, start, end, code=None, message=None, kind=None, color=None, labels=vec![], notes=vec![], helps=vec![], config=Config::new(ariadne::Config::default()), files=not_given()))]
+    #[pyo3(signature=(source, start, end=None, code=None, message=None, kind=None, color=None, labels=vec![], notes=vec![], helps=vec![], config=Config::default(), files=not_given(), includes=vec![], expansions=vec![], suggestions=vec![], name=None, encoding="utf-8", errors="strict"))]
     #[allow(clippy::too_many_arguments)]
     fn py_new(
         source: &Bound<'_, PyAny>,
-        start: usize,
-        end: usize,
+        start: &Bound<'_, PyAny>,
+        end: Option<usize>,
         code: Option<String>,
         message: Option<String>,
         kind: Option<&str>,
         color: Option<Color>,
         labels: Vec<Label>,
-        notes: Vec<String>,
-        helps: Vec<String>,
+        notes: Vec<SubDiagnostic>,
+        helps: Vec<SubDiagnostic>,
         config: Config,
         files: PyObject,
+        includes: Vec<Label>,
+        expansions: Vec<Expansion>,
+        suggestions: Vec<Suggestion>,
+        name: Option<&str>,
+        encoding: &str,
+        errors: &str,
     ) -> PyResult<Self> {
-        let span = start..end;
-        let source = Source::from_python(source)?;
+        let span = SpanArg::from_params(start, end)?;
+        let decoding = Decoding::new(encoding, errors);
+        let source = Source::from_python(source, &decoding)?.with_name(name);
+        let span = span.resolve(Some(&source), config.byte_indexed);
         let kind = ReportKind::from_params(kind, color)?;
-        let files = parse_files(files)?;
+        let files = parse_files(files, &decoding)?;
 
         let mut report = Report::new(source, span, config);
         report = report.set_params(code, message, kind, labels, notes, helps, files);
+        report = report.with_includes(includes, &decoding)?;
+        report.expansions = expansions;
+        report.suggestions = suggestions;
+        report.load_mapped_files(&decoding)?;
         Ok(report)
     }
 
+    #[staticmethod]
+    #[pyo3(signature=(node, source, *, code=None, message=None, kind=None, color=None, config=Config::default(), name=None, encoding="utf-8", errors="strict"))]
+    #[allow(clippy::too_many_arguments)]
+    fn from_ast(
+        node: &Bound<'_, PyAny>,
+        source: &Bound<'_, PyAny>,
+        code: Option<String>,
+        message: Option<String>,
+        kind: Option<&str>,
+        color: Option<Color>,
+        config: Config,
+        name: Option<&str>,
+        encoding: &str,
+        errors: &str,
+    ) -> PyResult<Self> {
+        let decoding = Decoding::new(encoding, errors);
+        let source = Source::from_python(source, &decoding)?.with_name(name);
+        let span = source.node_span(node, config.byte_indexed)?;
+        let kind = ReportKind::from_params(kind, color)?;
+        let report = Report::new(source, span, config);
+        let no_labels = Vec::new();
+        let (notes, helps, files) = (Vec::new(), Vec::new(), Vec::new());
+        let mut report = report.set_params(code, message, kind, no_labels, notes, helps, files);
+        report.load_mapped_files(&decoding)?;
+        Ok(report)
+    }
+
+    #[staticmethod]
+    #[pyo3(signature=(exc, source=None, *, code=None, kind=None, color=None, config=Config::default(), name=None, encoding="utf-8", errors="strict"))]
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn from_exception(
+        exc: &Bound<'_, PyAny>,
+        source: Option<&Bound<'_, PyAny>>,
+        code: Option<String>,
+        kind: Option<&str>,
+        color: Option<Color>,
+        config: Config,
+        name: Option<&str>,
+        encoding: &str,
+        errors: &str,
+    ) -> PyResult<Self> {
+        let decoding = Decoding::new(encoding, errors);
+        let converted = converters::convert(exc, source, &decoding, config.byte_indexed)?;
+        let kind = ReportKind::from_params(kind, color)?;
+        let helps = converted
+            .help()
+            .map(SubDiagnostic::Text)
+            .into_iter()
+            .collect();
+        let source = converted.source.with_name(name);
+        let report = Report::new(source, converted.span, config);
+        let (labels, notes, files) = (Vec::new(), Vec::new(), Vec::new());
+        Ok(report.set_params(
+            code,
+            Some(converted.message),
+            kind,
+            labels,
+            notes,
+            helps,
+            files,
+        ))
+    }
+
     #[pyo3(signature=(stderr=false))]
-    fn print(&self, stderr: bool) -> PyResult<()> {
-        let report = self.build_ariadne_report();
-        let files = ariadne::sources(self.prepare_files());
+    pub(crate) fn print(&self, stderr: bool) -> PyResult<()> {
         if stderr {
-            let writer = PyWriter::stderr()?;
-            report.write(files, writer)?;
+            self.write(&mut PyWriter::stderr()?, false, None)
         } else {
-            let writer = PyWriter::stdout()?;
-            report.write_for_stdout(files, writer)?;
+            self.write(&mut PyWriter::stdout()?, true, None)
         }
-        Ok(())
     }
 
     fn color(&mut self) -> Color {
@@ -145,48 +754,619 @@ impl Report {
         self.labels.push(label);
     }
 
-    #[pyo3(signature=(start, end, *, path=None, message=None, color=None, order=None, priority=None))]
+    #[pyo3(name = "label", signature=(start, end=None, *, path=None, message=None, color=None, order=None, priority=None, primary=false))]
     #[allow(clippy::too_many_arguments)]
-    fn label(
+    fn py_label(
         &mut self,
-        start: usize,
-        end: usize,
+        start: &Bound<'_, PyAny>,
+        end: Option<usize>,
         path: Option<&str>,
         message: Option<&str>,
         color: Option<Color>,
         order: Option<i32>,
         priority: Option<i32>,
+        primary: bool,
     ) -> PyResult<Label> {
-        let color = color.or_else(|| Some(Color::new(self.colors.next())));
-        let label = Label::py_new(start, end, path, message, color, order, priority)?;
-        self.labels.push(label.clone());
+        let target: Option<Arc<str>> = path.map(Arc::from);
+        let source = self.find_source(target.as_ref());
+        let span = SpanArg::from_params(start, end)?.resolve(source, self.config.byte_indexed);
+        self.label(
+            span.start, span.end, path, message, color, order, priority, primary,
+        )
+    }
+
+    #[pyo3(signature=(text, *, occurrence=1, lines=None, path=None, message=None, color=None, order=None, priority=None, primary=false))]
+    #[allow(clippy::too_many_arguments)]
+    fn label_text(
+        &mut self,
+        text: &str,
+        occurrence: usize,
+        lines: Option<(usize, usize)>,
+        path: Option<&str>,
+        message: Option<&str>,
+        color: Option<Color>,
+        order: Option<i32>,
+        priority: Option<i32>,
+        primary: bool,
+    ) -> PyResult<Label> {
+        if text.is_empty() || occurrence == 0 {
+            let msg = "Text must not be empty and occurrences start at 1";
+            return Err(PyValueError::new_err(msg));
+        }
+        let (start, end) = self.search(path, lines, |haystack, path| {
+            let mut found = haystack.match_indices(text).map(|(i, _)| i..i + text.len());
+            found.nth(occurrence - 1).ok_or_else(|| {
+                let msg = format!(
+                    "Occurrence {} of {:?} not found in '{}' ({} found)",
+                    occurrence,
+                    text,
+                    path,
+                    haystack.matches(text).count()
+                );
+                PyValueError::new_err(msg)
+            })
+        })?;
+        self.label(start, end, path, message, color, order, priority, primary)
+    }
+
+    #[pyo3(signature=(pattern, *, group=None, occurrence=1, lines=None, flags=0, path=None, message=None, color=None, order=None, priority=None, primary=false))]
+    #[allow(clippy::too_many_arguments)]
+    fn label_regex(
+        &mut self,
+        py: Python<'_>,
+        pattern: &Bound<'_, PyAny>,
+        group: Option<&Bound<'_, PyAny>>,
+        occurrence: usize,
+        lines: Option<(usize, usize)>,
+        flags: i32,
+        path: Option<&str>,
+        message: Option<&str>,
+        color: Option<Color>,
+        order: Option<i32>,
+        priority: Option<i32>,
+        primary: bool,
+    ) -> PyResult<Label> {
+        if occurrence == 0 {
+            let msg = "Occurrences start at 1";
+            return Err(PyValueError::new_err(msg));
+        }
+        let re = PyModule::import(py, "re")?;
+        let pattern = match pattern.is_instance_of::<PyString>() {
+            true => re.call_method1("compile", (pattern, flags))?,
+            false => pattern.clone(),
+        };
+        let group = match group {
+            Some(group) => group.clone(),
+            None => 0.into_bound_py_any(py)?,
+        };
+        let (start, end) = self.search(path, lines, |haystack, path| {
+            let pattern_repr = pattern.getattr("pattern")?.repr()?;
+            let matches = pattern.call_method1("finditer", (haystack,))?;
+            let Some(found) = matches.try_iter()?.nth(occurrence - 1) else {
+                let msg = format!(
+                    "Occurrence {} of pattern {} not found in '{}'",
+                    occurrence, pattern_repr, path
+                );
+                return Err(PyValueError::new_err(msg));
+            };
+            let span = found?.call_method1("span", (&group,))?;
+            let (start, end): (isize, isize) = span.extract()?;
+            if start < 0 {
+                let msg = format!("Group {} of pattern {} did not match", group, pattern_repr);
+                return Err(PyValueError::new_err(msg));
+            }
+            // Python returns char offsets
+            let start = char_to_byte(haystack, start as usize);
+            let end = char_to_byte(haystack, end as usize);
+            Ok(start..end)
+        })?;
+        self.label(start, end, path, message, color, order, priority, primary)
+    }
+
+    #[pyo3(signature=(node, *, path=None, message=None, color=None, order=None, priority=None, primary=false))]
+    #[allow(clippy::too_many_arguments)]
+    fn label_node(
+        &mut self,
+        node: &Bound<'_, PyAny>,
+        path: Option<&str>,
+        message: Option<&str>,
+        color: Option<Color>,
+        order: Option<i32>,
+        priority: Option<i32>,
+        primary: bool,
+    ) -> PyResult<Label> {
+        let target: Option<Arc<str>> = path.map(Arc::from);
+        let Some(source) = self.find_source(target.as_ref()) else {
+            let msg = format!("Unknown path: {}", path.unwrap_or_default());
+            return Err(PyValueError::new_err(msg));
+        };
+        let span = source.node_span(node, self.config.byte_indexed)?;
+        self.label(
+            span.start, span.end, path, message, color, order, priority, primary,
+        )
+    }
+
+    #[pyo3(signature=(label, *, encoding="utf-8", errors="strict"))]
+    fn add_include(&mut self, label: Label, encoding: &str, errors: &str) -> PyResult<()> {
+        self.push_include(label, &Decoding::new(encoding, errors))
+    }
+
+    #[pyo3(signature=(path, start, end, *, message=None, encoding="utf-8", errors="strict"))]
+    #[allow(clippy::too_many_arguments)]
+    fn included_from(
+        &mut self,
+        path: &Bound<'_, PyAny>,
+        start: usize,
+        end: usize,
+        message: Option<&str>,
+        encoding: &str,
+        errors: &str,
+    ) -> PyResult<Label> {
+        let path = from_path_or_str(path)?;
+        let label = Label::from_offsets(start, end, Some(&path), message, None, None, None, false)?;
+        self.push_include(label.clone(), &Decoding::new(encoding, errors))?;
         Ok(label)
     }
 
-    fn add_note(&mut self, note: String) {
-        self.notes.push(note);
+    fn add_expansion(&mut self, expansion: Expansion) {
+        self.expansions.push(expansion);
     }
 
-    fn add_help(&mut self, help: String) {
-        self.helps.push(help);
+    #[pyo3(signature=(name, start, end, *, path=None, definition=None))]
+    fn expanded_from(
+        &mut self,
+        name: String,
+        start: usize,
+        end: usize,
+        path: Option<&str>,
+        definition: Option<Label>,
+    ) -> PyResult<Expansion> {
+        let call_site = Label::from_offsets(start, end, path, None, None, None, None, false)?;
+        let expansion = Expansion::new(name, call_site, definition);
+        self.expansions.push(expansion.clone());
+        Ok(expansion)
+    }
+
+    #[pyo3(signature=(start, end, replacement, *, path=None, message=None, applicability="unspecified"))]
+    fn suggest(
+        &mut self,
+        start: usize,
+        end: usize,
+        replacement: String,
+        path: Option<&str>,
+        message: Option<String>,
+        applicability: &str,
+    ) -> PyResult<Suggestion> {
+        let suggestion = Suggestion::py_new(start, end, replacement, path, message, applicability)?;
+        self.suggestions.push(suggestion.clone());
+        Ok(suggestion)
+    }
+
+    fn add_suggestion(&mut self, suggestion: Suggestion) {
+        self.suggestions.push(suggestion);
+    }
+
+    #[getter]
+    fn suggestions(&self) -> Vec<Suggestion> {
+        self.suggestions.clone()
+    }
+
+    #[pyo3(signature=(note, start=None, end=None, *, path=None, labels=vec![]))]
+    fn add_note(
+        mut slf: PyRefMut<'_, Self>,
+        note: &Bound<'_, PyAny>,
+        start: Option<usize>,
+        end: Option<usize>,
+        path: Option<&Bound<'_, PyAny>>,
+        labels: Vec<Label>,
+    ) -> PyResult<()> {
+        check_not_self(slf.as_ptr(), note)?;
+        let note = slf.sub_diagnostic(ReportKind::Note, note, start, end, path, labels)?;
+        slf.notes.push(note);
+        Ok(())
+    }
+
+    #[pyo3(signature=(help, start=None, end=None, *, path=None, labels=vec![]))]
+    fn add_help(
+        mut slf: PyRefMut<'_, Self>,
+        help: &Bound<'_, PyAny>,
+        start: Option<usize>,
+        end: Option<usize>,
+        path: Option<&Bound<'_, PyAny>>,
+        labels: Vec<Label>,
+    ) -> PyResult<()> {
+        check_not_self(slf.as_ptr(), help)?;
+        let help = slf.sub_diagnostic(ReportKind::Help, help, start, end, path, labels)?;
+        slf.helps.push(help);
+        Ok(())
+    }
+
+    #[getter]
+    fn notes(&self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        self.notes.iter().map(|note| note.to_python(py)).collect()
+    }
+
+    #[getter]
+    fn helps(&self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        self.helps.iter().map(|help| help.to_python(py)).collect()
+    }
+
+    #[setter]
+    fn set_notes(slf: PyRefMut<'_, Self>, notes: Vec<SubDiagnostic>) -> PyResult<()> {
+        Self::replace_sub_diagnostics(slf, notes, false)
+    }
+
+    #[setter]
+    fn set_helps(slf: PyRefMut<'_, Self>, helps: Vec<SubDiagnostic>) -> PyResult<()> {
+        Self::replace_sub_diagnostics(slf, helps, true)
+    }
+
+    #[getter]
+    fn code(&self) -> Option<String> {
+        self.code.clone()
+    }
+
+    #[setter]
+    fn set_code(&mut self, code: Option<String>) {
+        self.code = code;
+    }
+
+    #[getter]
+    fn message(&self) -> Option<String> {
+        self.message.clone()
+    }
+
+    #[setter]
+    fn set_message(&mut self, message: Option<String>) {
+        self.message = message;
+    }
+
+    #[getter]
+    fn kind(&self) -> &str {
+        self.kind.name()
+    }
+
+    /// Custom kinds need a color, so they can only be set with copy()
+    #[setter]
+    fn set_kind(&mut self, kind: &str) -> PyResult<()> {
+        self.kind = ReportKind::from_params(Some(kind), None)?;
+        Ok(())
+    }
+
+    #[getter]
+    fn span(&self) -> (usize, usize) {
+        (self.span.start, self.span.end)
+    }
+
+    #[setter]
+    fn set_span(&mut self, span: (usize, usize)) -> PyResult<()> {
+        if span.0 > span.1 {
+            let msg = "Start index must be less than or equal to end index";
+            return Err(PyValueError::new_err(msg));
+        }
+        self.span = span.0..span.1;
+        Ok(())
+    }
+
+    #[getter]
+    fn labels<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyTuple>> {
+        PyTuple::new(py, self.labels.iter().cloned())
+    }
+
+    #[pyo3(signature=(**changes))]
+    fn copy(&self, py: Python<'_>, changes: Option<&Bound<'_, PyDict>>) -> PyResult<Py<Report>> {
+        let copy = Py::new(py, self.clone_ref(py))?;
+        if let Some(changes) = changes {
+            let copy = copy.bind(py);
+            copy.borrow_mut()
+                .apply_changes(copy.as_any(), changes, "copy")?;
+        }
+        Ok(copy)
+    }
+
+    /// Replace attributes in place. Nothing changes if any value is invalid.
+    #[pyo3(signature=(**changes))]
+    fn update(slf: &Bound<'_, Self>, changes: Option<&Bound<'_, PyDict>>) -> PyResult<()> {
+        let Some(changes) = changes else {
+            return Ok(());
+        };
+        let mut updated = slf.borrow().clone_ref(slf.py());
+        updated.apply_changes(slf.as_any(), changes, "update")?;
+        *slf.borrow_mut() = updated;
+        Ok(())
+    }
+
+    fn extend_labels(&mut self, labels: &Bound<'_, PyAny>) -> PyResult<()> {
+        // Collected first, so an invalid item leaves the report untouched
+        let labels = labels
+            .try_iter()?
+            .map(|label| label?.extract())
+            .collect::<PyResult<Vec<Label>>>()?;
+        self.labels.extend(labels);
+        Ok(())
+    }
+
+    #[pyo3(signature=(start, end=None, *, path=None, message=None, color=None, order=None, priority=None, primary=false))]
+    #[allow(clippy::too_many_arguments)]
+    fn with_label<'py>(
+        slf: Bound<'py, Self>,
+        start: &Bound<'py, PyAny>,
+        end: Option<usize>,
+        path: Option<&str>,
+        message: Option<&str>,
+        color: Option<Color>,
+        order: Option<i32>,
+        priority: Option<i32>,
+        primary: bool,
+    ) -> PyResult<Bound<'py, Self>> {
+        if let Ok(label) = start.downcast::<Label>() {
+            let has_options =
+                end.is_some() || path.is_some() || message.is_some() || color.is_some();
+            if has_options || order.is_some() || priority.is_some() || primary {
+                let msg = "Label options cannot be given together with a Label";
+                return Err(PyTypeError::new_err(msg));
+            }
+            slf.borrow_mut().add_label(label.get().clone());
+        } else {
+            slf.borrow_mut()
+                .py_label(start, end, path, message, color, order, priority, primary)?;
+        }
+        Ok(slf)
+    }
+
+    #[pyo3(signature=(note, start=None, end=None, *, path=None, labels=vec![]))]
+    fn with_note<'py>(
+        slf: Bound<'py, Self>,
+        note: &Bound<'py, PyAny>,
+        start: Option<usize>,
+        end: Option<usize>,
+        path: Option<&Bound<'py, PyAny>>,
+        labels: Vec<Label>,
+    ) -> PyResult<Bound<'py, Self>> {
+        Self::add_note(slf.borrow_mut(), note, start, end, path, labels)?;
+        Ok(slf)
+    }
+
+    #[pyo3(signature=(help, start=None, end=None, *, path=None, labels=vec![]))]
+    fn with_help<'py>(
+        slf: Bound<'py, Self>,
+        help: &Bound<'py, PyAny>,
+        start: Option<usize>,
+        end: Option<usize>,
+        path: Option<&Bound<'py, PyAny>>,
+        labels: Vec<Label>,
+    ) -> PyResult<Bound<'py, Self>> {
+        Self::add_help(slf.borrow_mut(), help, start, end, path, labels)?;
+        Ok(slf)
+    }
+
+    fn with_code(slf: Bound<'_, Self>, code: Option<String>) -> Bound<'_, Self> {
+        slf.borrow_mut().code = code;
+        slf
+    }
+
+    fn __eq__(&self, py: Python<'_>, other: PyRef<'_, Report>) -> PyResult<bool> {
+        if std::ptr::eq(self, &*other) {
+            return Ok(true);
+        }
+        self.equals(py, &other)
+    }
+
+    // Reports are mutable
+    #[classattr]
+    const __hash__: Option<PyObject> = None;
+
+    /// Reports are created empty and then filled with their state, so that
+    /// unpickling never reads files from disk.
+    #[allow(clippy::type_complexity)]
+    fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<(
+        Bound<'py, PyType>,
+        (&'static str, usize, usize),
+        Bound<'py, PyDict>,
+    )> {
+        let color = match &self.kind {
+            ReportKind::Custom(_, color) => Some(*color),
+            _ => None,
+        };
+        let state = PyDict::new(py);
+        state.set_item("source", self.source.clone())?;
+        state.set_item("span", (self.span.start, self.span.end))?;
+        state.set_item("config", self.config.clone())?;
+        state.set_item("code", &self.code)?;
+        state.set_item("message", &self.message)?;
+        state.set_item("kind", self.kind.name())?;
+        state.set_item("color", color)?;
+        state.set_item("labels", self.labels.clone())?;
+        state.set_item("notes", self.notes(py)?)?;
+        state.set_item("helps", self.helps(py)?)?;
+        state.set_item("files", self.files.clone())?;
+        state.set_item("includes", self.includes.clone())?;
+        state.set_item("expansions", self.expansions.clone())?;
+        state.set_item("suggestions", self.suggestions.clone())?;
+        state.set_item("colors", self.colors.count())?;
+        Ok((py.get_type::<Report>(), ("", 0, 0), state))
+    }
+
+    fn __setstate__(&mut self, state: &Bound<'_, PyDict>) -> PyResult<()> {
+        let item = |key: &str| match state.get_item(key)? {
+            Some(value) => Ok(value),
+            None => Err(PyKeyError::new_err(key.to_string())),
+        };
+        let (start, end): (usize, usize) = item("span")?.extract()?;
+        let kind: String = item("kind")?.extract()?;
+        self.source = item("source")?.extract()?;
+        self.span = start..end;
+        self.config = item("config")?.extract()?;
+        self.code = item("code")?.extract()?;
+        self.message = item("message")?.extract()?;
+        self.kind = ReportKind::from_params(Some(&kind), item("color")?.extract()?)?;
+        self.labels = item("labels")?.extract()?;
+        self.notes = item("notes")?.extract()?;
+        self.helps = item("helps")?.extract()?;
+        self.files = item("files")?.extract()?;
+        self.includes = item("includes")?.extract()?;
+        self.expansions = item("expansions")?.extract()?;
+        self.suggestions = item("suggestions")?.extract()?;
+        self.colors = ColorSequence::at(item("colors")?.extract()?);
+        Ok(())
+    }
+
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        let mut args = vec![
+            format!("{:?}", self.source.path),
+            self.span.start.to_string(),
+            self.span.end.to_string(),
+        ];
+        if let Some(code) = &self.code {
+            args.push(format!("code={code:?}"));
+        }
+        if let Some(message) = &self.message {
+            args.push(format!("message={message:?}"));
+        }
+        match &self.kind {
+            ReportKind::Error => {}
+            ReportKind::Custom(name, color) => args.push(format!("kind={name:?}, color={color:?}")),
+            kind => args.push(format!("kind={:?}", kind.name())),
+        }
+        if !self.labels.is_empty() {
+            let labels: Vec<String> = self.labels.iter().map(Label::__repr__).collect();
+            args.push(format!("labels=[{}]", labels.join(", ")));
+        }
+        for (name, subs) in [("notes", &self.notes), ("helps", &self.helps)] {
+            if !subs.is_empty() {
+                let subs = subs
+                    .iter()
+                    .map(|sub| sub.repr(py))
+                    .collect::<PyResult<Vec<_>>>()?;
+                args.push(format!("{name}=[{}]", subs.join(", ")));
+            }
+        }
+        Ok(format!("Report({})", args.join(", ")))
+    }
+}
+
+/// A note or help message. Sub-diagnostics that point to code are stored as
+/// child reports and rendered below the main report.
+pub enum SubDiagnostic {
+    Text(String),
+    Report(Py<Report>),
+}
+
+impl SubDiagnostic {
+    fn text(&self) -> Option<&String> {
+        match self {
+            SubDiagnostic::Text(text) => Some(text),
+            SubDiagnostic::Report(_) => None,
+        }
+    }
+
+    fn report(&self) -> Option<&Py<Report>> {
+        match self {
+            SubDiagnostic::Text(_) => None,
+            SubDiagnostic::Report(report) => Some(report),
+        }
+    }
+
+    fn to_python(&self, py: Python<'_>) -> PyResult<PyObject> {
+        match self {
+            SubDiagnostic::Text(text) => text.into_py_any(py),
+            SubDiagnostic::Report(report) => Ok(report.clone_ref(py).into_any()),
+        }
+    }
+
+    fn clone_ref(&self, py: Python<'_>) -> Self {
+        match self {
+            SubDiagnostic::Text(text) => SubDiagnostic::Text(text.clone()),
+            SubDiagnostic::Report(report) => SubDiagnostic::Report(report.clone_ref(py)),
+        }
+    }
+
+    fn repr(&self, py: Python<'_>) -> PyResult<String> {
+        match self {
+            SubDiagnostic::Text(text) => Ok(format!("{text:?}")),
+            SubDiagnostic::Report(report) => report.bind(py).try_borrow()?.__repr__(py),
+        }
+    }
+
+    fn all_equal(py: Python<'_>, a: &[SubDiagnostic], b: &[SubDiagnostic]) -> PyResult<bool> {
+        if a.len() != b.len() {
+            return Ok(false);
+        }
+        for pair in a.iter().zip(b) {
+            let equal = match pair {
+                (SubDiagnostic::Text(a), SubDiagnostic::Text(b)) => a == b,
+                (SubDiagnostic::Report(a), SubDiagnostic::Report(b)) if a.is(b) => true,
+                (SubDiagnostic::Report(a), SubDiagnostic::Report(b)) => a
+                    .bind(py)
+                    .try_borrow()?
+                    .equals(py, &*b.bind(py).try_borrow()?)?,
+                _ => false,
+            };
+            if !equal {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl<'py> FromPyObject<'py> for SubDiagnostic {
+    fn extract_bound(obj: &Bound<'py, PyAny>) -> PyResult<Self> {
+        if let Ok(report) = obj.downcast::<Report>() {
+            return Ok(SubDiagnostic::Report(report.clone().unbind()));
+        }
+        match obj.extract() {
+            Ok(text) => Ok(SubDiagnostic::Text(text)),
+            Err(_) => {
+                let msg = "Notes and helps must be strings or Report instances";
+                Err(PyTypeError::new_err(msg))
+            }
+        }
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub enum ReportKind {
     Error,
     Warning,
     Advice,
+    Note,
+    Help,
     // We must own the custom kind string to avoid lifetime issues
     Custom(Arc<str>, Color),
 }
 
 impl ReportKind {
+    fn name(&self) -> &str {
+        match self {
+            ReportKind::Error => "error",
+            ReportKind::Warning => "warning",
+            ReportKind::Advice => "advice",
+            ReportKind::Note => "note",
+            ReportKind::Help => "help",
+            ReportKind::Custom(name, _) => name,
+        }
+    }
+
+    /// Kind matching the level of a log record.
+    pub fn from_level(level: i32) -> Self {
+        match level {
+            ..=20 => ReportKind::Advice,
+            21..=30 => ReportKind::Warning,
+            _ => ReportKind::Error,
+        }
+    }
+
     fn from_params(name: Option<&str>, color: Option<Color>) -> PyResult<Self> {
         match (name, color) {
             (None, None) | (Some("error"), None) => Ok(ReportKind::Error),
             (Some("warning"), None) | (Some("warn"), None) => Ok(ReportKind::Warning),
             (Some("advice"), None) => Ok(ReportKind::Advice),
+            (Some("note"), None) => Ok(ReportKind::Note),
+            (Some("help"), None) => Ok(ReportKind::Help),
             (None, Some(_)) => {
                 let msg = "Color specified without a name for custom report kind";
                 Err(PyValueError::new_err(msg))
@@ -195,8 +1375,7 @@ impl ReportKind {
                 if name.is_empty() {
                     let msg = "Custom report kind name cannot be empty";
                     return Err(PyValueError::new_err(msg));
-                } else if name == "error" || name == "warning" || name == "advice" || name == "warn"
-                {
+                } else if ["error", "warning", "warn", "advice", "note", "help"].contains(&name) {
                     let msg = format!("Cannot set custom color for {}", name);
                     return Err(PyValueError::new_err(msg));
                 }
@@ -214,45 +1393,47 @@ impl ReportKind {
             ReportKind::Error => ariadne::ReportKind::Error,
             ReportKind::Warning => ariadne::ReportKind::Warning,
             ReportKind::Advice => ariadne::ReportKind::Advice,
+            ReportKind::Note => ariadne::ReportKind::Custom("Note", ariadne::Color::Fixed(115)),
+            ReportKind::Help => ariadne::ReportKind::Custom("Help", ariadne::Color::Fixed(115)),
             ReportKind::Custom(name, color) => ariadne::ReportKind::Custom(name, color.inner),
         }
     }
-}
 
-pub struct Source {
-    path: Arc<str>,
-    source: Arc<str>,
+    fn color(&self) -> ariadne::Color {
+        match self {
+            ReportKind::Error => ariadne::Color::Red,
+            ReportKind::Warning => ariadne::Color::Yellow,
+            ReportKind::Advice => ariadne::Color::Fixed(147),
+            ReportKind::Note | ReportKind::Help => ariadne::Color::Fixed(115),
+            ReportKind::Custom(_, color) => color.inner,
+        }
+    }
+
+    fn is_custom(&self) -> bool {
+        matches!(
+            self,
+            ReportKind::Note | ReportKind::Help | ReportKind::Custom(..)
+        )
+    }
 }
 
-impl Source {
-    fn new(path: Arc<str>, source: Arc<str>) -> Self {
-        Source { path, source }
-    }
-
-    fn from_python(source: &Bound<'_, PyAny>) -> PyResult<Self> {
-        // Strings
-        if let Ok(source) = source.downcast::<PyString>() {
-            let source = source.to_str()?.into();
-            Ok(Source::new("<string>".into(), source))
-        // Paths
-        } else if let Ok(path) = from_path(source) {
-            let source = std::fs::read_to_string(path.to_string()).map_err(|e| {
-                let msg = format!("Failed to read file '{}': {}", path, e);
-                PyFileNotFoundError::new_err(msg)
-            })?;
-            Ok(Source::new(path, source.into()))
-        // File-like
-        } else if let Ok(source) = from_file_like(source) {
-            Ok(source)
+/// Remove the ANSI escape sequences from the first line of a rendered report.
+fn strip_header_colors(buffer: &[u8]) -> Vec<u8> {
+    let end = buffer
+        .iter()
+        .position(|&b| b == b'\n')
+        .unwrap_or(buffer.len());
+    let mut output = Vec::with_capacity(buffer.len());
+    let mut bytes = buffer[..end].iter();
+    while let Some(&byte) = bytes.next() {
+        if byte == 0x1b {
+            bytes.by_ref().find(|&&b| b == b'm');
         } else {
-            let msg = "Expected a string, path or file-like object";
-            Err(PyErr::new::<PyTypeError, _>(msg))
+            output.push(byte);
         }
     }
-
-    fn pair(&self) -> (Arc<str>, Arc<str>) {
-        (self.path.clone(), self.source.clone())
-    }
+    output.extend_from_slice(&buffer[end..]);
+    output
 }
 
 pub struct PyWriter {
@@ -299,6 +1480,31 @@ impl Write for PyWriter {
     }
 }
 
+/// Refuse a child that is the report itself or contains it among its nested
+/// sub-diagnostics, since rendering and comparing would never end.
+///
+/// Reports already form a tree, so the walk stops before reaching the report
+/// and never borrows it.
+fn check_not_self(report: *mut pyo3::ffi::PyObject, child: &Bound<'_, PyAny>) -> PyResult<()> {
+    if report == child.as_ptr() {
+        let msg = "A report cannot be its own sub-diagnostic";
+        return Err(PyValueError::new_err(msg));
+    }
+    let Ok(child) = child.downcast::<Report>() else {
+        return Ok(());
+    };
+    let child = child.try_borrow()?;
+    for grandchild in child
+        .notes
+        .iter()
+        .chain(&child.helps)
+        .filter_map(SubDiagnostic::report)
+    {
+        check_not_self(report, grandchild.bind(child.py()))?;
+    }
+    Ok(())
+}
+
 fn not_given() -> PyObject {
     Python::with_gil(|py| {
         let bound = PyList::empty(py).into_any();
@@ -306,11 +1512,11 @@ fn not_given() -> PyObject {
     })
 }
 
-fn parse_files(files: PyObject) -> PyResult<Vec<Source>> {
-    Python::with_gil(|py| parse_files_bounded(files.bind(py)))
+fn parse_files(files: PyObject, decoding: &Decoding) -> PyResult<Vec<Source>> {
+    Python::with_gil(|py| parse_files_bounded(files.bind(py), decoding))
 }
 
-fn parse_files_bounded(files: &Bound<'_, PyAny>) -> PyResult<Vec<Source>> {
+fn parse_files_bounded(files: &Bound<'_, PyAny>, decoding: &Decoding) -> PyResult<Vec<Source>> {
     if files.is_none() {
         return Ok(Vec::new());
     }
@@ -319,7 +1525,7 @@ fn parse_files_bounded(files: &Bound<'_, PyAny>) -> PyResult<Vec<Source>> {
     if let Ok(list) = files.downcast::<PyList>() {
         let mut sources = Vec::new();
         for item in list.iter() {
-            sources.push(Source::from_python(&item)?);
+            sources.push(Source::from_python(&item, decoding)?);
         }
         return Ok(sources);
     }
@@ -329,8 +1535,7 @@ fn parse_files_bounded(files: &Bound<'_, PyAny>) -> PyResult<Vec<Source>> {
         let mut sources = Vec::new();
         for item in list.iter() {
             let path = from_path_or_str(&item.0)?;
-            let source = item.1.downcast::<PyString>()?.to_str()?;
-            sources.push(Source::new(path, source.into()));
+            sources.push(Source::from_data(path, &item.1, decoding)?);
         }
         return Ok(sources);
     }
@@ -339,7 +1544,7 @@ fn parse_files_bounded(files: &Bound<'_, PyAny>) -> PyResult<Vec<Source>> {
     if let Ok(iter) = files.downcast::<PyIterator>() {
         let mut sources = Vec::new();
         for item in iter {
-            sources.push(Source::from_python(&item?)?);
+            sources.push(Source::from_python(&item?, decoding)?);
         }
         return Ok(sources);
     }
@@ -347,41 +1552,3 @@ fn parse_files_bounded(files: &Bound<'_, PyAny>) -> PyResult<Vec<Source>> {
     let msg = "Expected a list of files or dictionary mapping paths to sources";
     Err(PyTypeError::new_err(msg))
 }
-
-fn from_path_or_str(obj: &Bound<'_, PyAny>) -> PyResult<Arc<str>> {
-    // Strings
-    if let Ok(path) = obj.downcast::<PyString>() {
-        Ok(path.to_str()?.into())
-
-    // Path objects
-    } else if let Ok(path) = from_path(obj) {
-        Ok(path)
-    } else {
-        let msg = "Expected a string or Path object";
-        Err(PyTypeError::new_err(msg))
-    }
-}
-
-fn from_path(obj: &Bound<'_, PyAny>) -> PyResult<Arc<str>> {
-    Python::with_gil(|py| {
-        let path_type = PyModule::import(py, "pathlib")?.getattr("Path")?;
-        if obj.is_instance(&path_type)? {
-            Ok(obj.to_string().into())
-        } else {
-            Err(PyTypeError::new_err("Expected a Path object or a string"))
-        }
-    })
-}
-
-fn from_file_like(obj: &Bound<'_, PyAny>) -> PyResult<Source> {
-    let source = obj.call_method0("read")?;
-    let source = source.downcast::<PyString>()?.to_str()?;
-    let path: Arc<str> = match obj.getattr("name") {
-        Ok(value) => {
-            let str = value.downcast::<PyString>()?.to_str()?;
-            str.into()
-        }
-        Err(_) => "<string>".into(),
-    };
-    Ok(Source::new(path, source.into()))
-}
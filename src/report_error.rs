@@ -0,0 +1,108 @@
+use crate::report::Report;
+use pyo3::exceptions::{PyException, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyList, PyTuple};
+
+/// An exception carrying one or more reports.
+///
+/// Uncaught errors show the rendered reports in the traceback, with colors
+/// disabled since tracebacks may be written to files or logs.
+#[pyclass(module = "theseus", extends=PyException)]
+pub struct ReportError {
+    pub(crate) reports: Vec<Py<Report>>,
+}
+
+impl ReportError {
+    /// Collect the reports of the ReportErrors in a (possibly nested)
+    /// exception group.
+    fn collect(group: &Bound<'_, PyAny>, reports: &mut Vec<Py<Report>>) -> PyResult<()> {
+        for exc in group.getattr("exceptions")?.downcast::<PyTuple>()? {
+            if let Ok(error) = exc.downcast::<ReportError>() {
+                let error = error.borrow();
+                reports.extend(
+                    error
+                        .reports
+                        .iter()
+                        .map(|report| report.clone_ref(exc.py())),
+                );
+            } else if exc.hasattr("exceptions")? {
+                Self::collect(&exc, reports)?;
+            } else {
+                let name = exc.get_type().name()?;
+                let msg = format!("Exception groups can only contain ReportErrors, got {name}");
+                return Err(PyTypeError::new_err(msg));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl ReportError {
+    #[new]
+    fn py_new(reports: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let mut collected = Vec::new();
+        if let Ok(report) = reports.downcast::<Report>() {
+            collected.push(report.clone().unbind());
+        } else if reports.hasattr("exceptions")? {
+            Self::collect(reports, &mut collected)?;
+        } else {
+            let msg = "Expected a Report, a list of reports or an exception group";
+            for report in reports.try_iter().map_err(|_| PyTypeError::new_err(msg))? {
+                let report = report?;
+                let report = report
+                    .downcast::<Report>()
+                    .map_err(|_| PyTypeError::new_err(msg))?;
+                collected.push(report.clone().unbind());
+            }
+        }
+        if collected.is_empty() {
+            let msg = "ReportError requires at least one report";
+            return Err(PyValueError::new_err(msg));
+        }
+        Ok(ReportError { reports: collected })
+    }
+
+    #[getter]
+    fn report(&self, py: Python<'_>) -> Py<Report> {
+        self.reports[0].clone_ref(py)
+    }
+
+    #[getter]
+    fn reports<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        PyList::new(py, self.reports.iter().map(|report| report.clone_ref(py)))
+    }
+
+    /// Split into an exception group with one ReportError per report.
+    #[pyo3(signature=(message=None))]
+    fn group<'py>(&self, py: Python<'py>, message: Option<String>) -> PyResult<Bound<'py, PyAny>> {
+        let group_type = match PyModule::import(py, "builtins")?.getattr("ExceptionGroup") {
+            Ok(group_type) => group_type,
+            // Python < 3.11 needs the exceptiongroup backport
+            Err(_) => PyModule::import(py, "exceptiongroup")?.getattr("ExceptionGroup")?,
+        };
+        let errors = self
+            .reports
+            .iter()
+            .map(|report| {
+                let error = ReportError {
+                    reports: vec![report.clone_ref(py)],
+                };
+                Bound::new(py, error)
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        let message = message.unwrap_or_else(|| match errors.len() {
+            1 => "1 error".to_string(),
+            n => format!("{n} errors"),
+        });
+        group_type.call1((message, errors))
+    }
+
+    fn __str__(&self, py: Python<'_>) -> PyResult<String> {
+        let mut text = String::new();
+        for report in &self.reports {
+            text.push_str(&report.bind(py).borrow().render(false)?);
+        }
+        Ok(text.trim_end().to_string())
+    }
+}
@@ -0,0 +1,958 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::_Span;
+use crate::config::Config;
+use crate::source_map::SourceMap;
+use pyo3::exceptions::{PyFileNotFoundError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::sync::GILOnceCell;
+use pyo3::types::{PyBytes, PyString, PyTuple};
+
+/// A source file, decoded and normalized.
+///
+/// Sources keep enough information to translate offsets into the original
+/// input (which may contain a BOM, CRLF line endings or be encoded in
+/// something other than UTF-8) to offsets into the normalized text.
+#[pyclass(module = "theseus", frozen)]
+#[derive(Clone)]
+pub struct Source {
+    pub(crate) path: Arc<str>,
+    pub(crate) text: Arc<str>,
+    // The text as a Python str, shared by all copies of the source, so the
+    // pickle memo stores it once for reports built from the same source
+    py_text: Arc<GILOnceCell<Py<PyString>>>,
+    offsets: Option<Arc<OffsetMap>>,
+    line_offset: usize,
+    column_offset: usize,
+    // Names such as "Cell [3]" are not affected by the path display policy
+    virtual_path: bool,
+    cells: Option<Arc<Cells>>,
+    source_map: Option<Arc<SourceMap>>,
+}
+
+impl Source {
+    pub fn new(path: Arc<str>, text: &str) -> Self {
+        Self::normalized(path, text, ByteOffsets::Utf8)
+    }
+
+    fn normalized(path: Arc<str>, text: &str, bytes: ByteOffsets) -> Self {
+        let (text, offsets) = normalize(text, bytes);
+        Source {
+            path,
+            text,
+            py_text: Arc::default(),
+            offsets: offsets.map(Arc::new),
+            line_offset: 0,
+            column_offset: 0,
+            virtual_path: false,
+            cells: None,
+            source_map: None,
+        }
+    }
+
+    /// Create a notebook source from a list of cells.
+    ///
+    /// Global offsets refer to the text of all cells joined by newlines, and
+    /// each cell is rendered as a separate file.
+    pub fn from_cells(path: Arc<str>, cells: Vec<(Arc<str>, &str)>) -> Self {
+        let mut spans = Vec::with_capacity(cells.len());
+        let mut sources = Vec::with_capacity(cells.len());
+        let (mut char, mut byte) = (0, 0);
+        for (name, text) in cells {
+            let chars = char..char + text.chars().count();
+            let bytes = byte..byte + text.len();
+            (char, byte) = (chars.end + 1, bytes.end + 1);
+            spans.push(CellSpan { chars, bytes });
+            let mut cell = Source::new(name, text);
+            cell.virtual_path = true;
+            sources.push(cell);
+        }
+        let text: Vec<&str> = sources.iter().map(|cell| &*cell.text).collect();
+        let mut source = Source::new(path, &text.join("\n"));
+        source.cells = Some(Arc::new(Cells { sources, spans }));
+        source
+    }
+
+    pub fn from_python(source: &Bound<'_, PyAny>, decoding: &Decoding) -> PyResult<Self> {
+        // Sources
+        if let Ok(source) = source.downcast::<Source>() {
+            Ok(source.get().clone())
+        // Strings and bytes
+        } else if source.is_instance_of::<PyString>() || source.is_instance_of::<PyBytes>() {
+            Self::from_data("<string>".into(), source, decoding)
+        // Paths
+        } else if let Ok(path) = from_path(source) {
+            Self::from_file(path, decoding)
+        // File-like
+        } else if source.hasattr("read")? {
+            from_file_like(source, decoding)
+        } else {
+            let msg = "Expected a string, bytes, path or file-like object";
+            Err(PyErr::new::<PyTypeError, _>(msg))
+        }
+    }
+
+    /// Read and decode a file from disk.
+    pub fn from_file(path: Arc<str>, decoding: &Decoding) -> PyResult<Self> {
+        let data = std::fs::read(path.as_ref()).map_err(|e| {
+            let msg = format!("Failed to read file '{}': {}", path, e);
+            PyFileNotFoundError::new_err(msg)
+        })?;
+        Self::from_bytes(path, &data, decoding)
+    }
+
+    /// Create source from the contents of a file, given either as str or bytes.
+    pub fn from_data(
+        path: Arc<str>,
+        data: &Bound<'_, PyAny>,
+        decoding: &Decoding,
+    ) -> PyResult<Self> {
+        if let Ok(text) = data.downcast::<PyString>() {
+            Ok(Source::new(path, text.to_str()?))
+        } else if let Ok(data) = data.downcast::<PyBytes>() {
+            Self::from_bytes(path, data.as_bytes(), decoding)
+        } else {
+            let msg = "Expected a string or bytes";
+            Err(PyTypeError::new_err(msg))
+        }
+    }
+
+    pub fn from_bytes(path: Arc<str>, data: &[u8], decoding: &Decoding) -> PyResult<Self> {
+        if decoding.is_utf8() {
+            if let Ok(text) = std::str::from_utf8(data) {
+                return Ok(Self::normalized(path, text, ByteOffsets::Utf8));
+            }
+        }
+        let (text, bytes) = decoding.decode(data)?;
+        Ok(Self::normalized(path, &text, bytes))
+    }
+
+    /// Replace the path used to identify and display this source.
+    pub fn with_name(mut self, name: Option<&str>) -> Self {
+        if let Some(name) = name {
+            self.path = name.into();
+        }
+        self
+    }
+
+    /// Place the source at the given line and column of a larger host file.
+    pub fn with_position(mut self, line_offset: usize, column_offset: usize) -> Self {
+        self.line_offset = line_offset;
+        self.column_offset = column_offset;
+        self
+    }
+
+    pub fn with_source_map(mut self, source_map: Option<SourceMap>) -> Self {
+        self.source_map = source_map.map(Arc::new);
+        self
+    }
+
+    /// Paths of the original files referenced by the source map.
+    pub fn mapped_paths(&self) -> Vec<Arc<str>> {
+        match &self.source_map {
+            Some(source_map) => source_map.paths().cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Find the original location of a span, if this is generated code.
+    pub fn map_span(&self, span: &Range<usize>) -> Option<(Arc<str>, Range<usize>)> {
+        self.source_map.as_ref()?.map(span)
+    }
+
+    /// The normalized text under the given span.
+    pub fn snippet(&self, span: Range<usize>, byte_indexed: bool) -> String {
+        let span = self.translate(span, byte_indexed);
+        let span = span.start - self.padding()..span.end - self.padding();
+        if byte_indexed {
+            self.text.get(span).unwrap_or_default().into()
+        } else {
+            self.text
+                .chars()
+                .skip(span.start)
+                .take(span.len())
+                .collect()
+        }
+    }
+
+    /// Return the path and the text rendered by ariadne.
+    ///
+    /// Fragments of a larger file are padded with empty lines and spaces, so
+    /// line and column numbers refer to their position in the host file.
+    pub fn pair(&self) -> (Arc<str>, Arc<str>) {
+        if self.line_offset == 0 && self.column_offset == 0 {
+            return (self.path.clone(), self.text.clone());
+        }
+        let mut text = String::with_capacity(self.padding() + self.text.len());
+        text.push_str(&"\n".repeat(self.line_offset));
+        text.push_str(&" ".repeat(self.column_offset));
+        text.push_str(&self.text);
+        (self.path.clone(), text.into())
+    }
+
+    fn padding(&self) -> usize {
+        self.line_offset + self.column_offset
+    }
+
+    /// Name shown in the report header, according to the config path policy.
+    ///
+    /// Pseudo-paths such as `<string>` are not real files and are shown as-is.
+    pub fn display_name(&self, py: Python<'_>, config: &Config) -> PyResult<String> {
+        if self.virtual_path || (self.path.starts_with('<') && self.path.ends_with('>')) {
+            Ok(self.path.to_string())
+        } else {
+            config.path_display.display(py, &self.path)
+        }
+    }
+
+    /// Sources rendered as separate files: either the cells of a notebook or
+    /// the source itself.
+    pub fn parts(&self) -> Vec<&Source> {
+        match &self.cells {
+            Some(cells) => cells.sources.iter().collect(),
+            None => vec![self],
+        }
+    }
+
+    /// Find this source or one of its cells by path.
+    pub fn find(&self, path: &str) -> Option<&Source> {
+        if &*self.path == path {
+            return Some(self);
+        }
+        let cells = self.cells.as_ref()?;
+        cells.sources.iter().find(|cell| &*cell.path == path)
+    }
+
+    /// Resolve a span in the original input into an ariadne span.
+    pub fn locate(&self, span: Range<usize>, byte_indexed: bool) -> _Span {
+        match &self.cells {
+            Some(cells) => cells.locate(span, byte_indexed),
+            None => (self.path.clone(), self.translate(span, byte_indexed)),
+        }
+    }
+
+    /// Byte range of the normalized text covering the given lines.
+    ///
+    /// Lines are 1-based and inclusive, numbered as shown in reports.
+    pub fn line_range(&self, first: usize, last: usize) -> PyResult<Range<usize>> {
+        let starts: Vec<usize> = std::iter::once(0)
+            .chain(self.text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        let first = first.checked_sub(self.line_offset + 1);
+        let last = last.checked_sub(self.line_offset + 1);
+        match (first, last) {
+            (Some(first), Some(last)) if first <= last && last < starts.len() => {
+                let end = starts.get(last + 1).map_or(self.text.len(), |&end| end - 1);
+                Ok(starts[first]..end)
+            }
+            _ => {
+                let msg = format!("Invalid line range for '{}'", self.path);
+                Err(PyValueError::new_err(msg))
+            }
+        }
+    }
+
+    /// Span in the original input of a Python AST node or tokenize token.
+    ///
+    /// AST nodes have UTF-8 byte columns, while tokenize columns count chars.
+    /// Lines are numbered from the start of this source.
+    pub fn node_span(&self, node: &Bound<'_, PyAny>, byte_indexed: bool) -> PyResult<Range<usize>> {
+        let (start, end) = if node.hasattr("col_offset")? {
+            let line: usize = node.getattr("lineno")?.extract()?;
+            let col: usize = node.getattr("col_offset")?.extract()?;
+            let end_line: Option<usize> = node.getattr("end_lineno")?.extract()?;
+            let end_col: Option<usize> = node.getattr("end_col_offset")?.extract()?;
+            let start = self.line_col(line, col, true);
+            let end = match (end_line, end_col) {
+                (Some(line), Some(col)) => self.line_col(line, col, true),
+                _ => start,
+            };
+            (start, end)
+        } else if node.hasattr("start")? && node.hasattr("end")? {
+            let (line, col): (usize, usize) = node.getattr("start")?.extract()?;
+            let (end_line, end_col): (usize, usize) = node.getattr("end")?.extract()?;
+            (
+                self.line_col(line, col, false),
+                self.line_col(end_line, end_col, false),
+            )
+        } else {
+            let msg = "Expected an AST node or a tokenize token with a position";
+            return Err(PyTypeError::new_err(msg));
+        };
+        Ok(self.original_span(start..end.max(start), byte_indexed))
+    }
+
+    /// Byte offset in the normalized text of a 1-based line and a 0-based
+    /// column, clamped to the end of the line.
+    pub fn line_col(&self, line: usize, col: usize, col_bytes: bool) -> usize {
+        let start = match line {
+            0 | 1 => 0,
+            _ => match self.text.match_indices('\n').nth(line - 2) {
+                Some((i, _)) => i + 1,
+                None => return self.text.len(),
+            },
+        };
+        let text = &self.text[start..];
+        let text = &text[..text.find('\n').unwrap_or(text.len())];
+        match col_bytes {
+            true => start + floor_char_boundary(text, col.min(text.len())),
+            false => start + char_to_byte(text, col),
+        }
+    }
+
+    /// Span in the original input of a byte range in the normalized text.
+    pub fn original_span(&self, span: Range<usize>, byte_indexed: bool) -> Range<usize> {
+        let Some(map) = &self.offsets else {
+            return match byte_indexed {
+                true => span,
+                false => {
+                    let start = self.text[..span.start].chars().count();
+                    start..start + self.text[span].chars().count()
+                }
+            };
+        };
+        let start = map.original(&self.text, span.start, byte_indexed);
+        let end = match span.is_empty() {
+            true => start,
+            false => map.original_end(&self.text, span.end, byte_indexed),
+        };
+        start..end
+    }
+
+    /// Convert a span in the original input between char and byte offsets.
+    pub fn convert_span(
+        &self,
+        span: Range<usize>,
+        from_bytes: bool,
+        to_bytes: bool,
+    ) -> Range<usize> {
+        if from_bytes == to_bytes {
+            return span;
+        }
+        let span = self.translate(span, from_bytes);
+        let (start, end) = (span.start - self.padding(), span.end - self.padding());
+        let span = match from_bytes {
+            true => start..end,
+            false => char_to_byte(&self.text, start)..char_to_byte(&self.text, end),
+        };
+        self.original_span(span, to_bytes)
+    }
+
+    /// Translate a span in the original input to a span in the normalized text.
+    pub fn translate(&self, span: Range<usize>, byte_indexed: bool) -> Range<usize> {
+        let span = match &self.offsets {
+            None => span,
+            Some(map) => {
+                let start = map.translate(&self.text, span.start, byte_indexed);
+                let end = map.translate(&self.text, span.end, byte_indexed);
+                start..end
+            }
+        };
+        let span = if byte_indexed {
+            // Ariadne panics if byte offsets fall inside a char
+            let start = floor_char_boundary(&self.text, span.start);
+            let end = floor_char_boundary(&self.text, span.end);
+            start..end
+        } else {
+            span
+        };
+        // Padding is ASCII, so it has the same length in chars and bytes
+        span.start + self.padding()..span.end + self.padding()
+    }
+}
+
+#[pymethods]
+impl Source {
+    #[new]
+    #[pyo3(signature=(source, *, name=None, encoding="utf-8", errors="strict", line_offset=None, start_line=None, column_offset=0, source_map=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn py_new(
+        source: &Bound<'_, PyAny>,
+        name: Option<&str>,
+        encoding: &str,
+        errors: &str,
+        line_offset: Option<usize>,
+        start_line: Option<usize>,
+        column_offset: usize,
+        source_map: Option<SourceMap>,
+    ) -> PyResult<Self> {
+        let line_offset = match (line_offset, start_line) {
+            (None, None) => 0,
+            (Some(offset), None) => offset,
+            (None, Some(0)) => {
+                let msg = "start_line must be at least 1";
+                return Err(PyValueError::new_err(msg));
+            }
+            (None, Some(line)) => line - 1,
+            (Some(_), Some(_)) => {
+                let msg = "Cannot specify both line_offset and start_line";
+                return Err(PyValueError::new_err(msg));
+            }
+        };
+        let source = Source::from_python(source, &Decoding::new(encoding, errors))?;
+        Ok(source
+            .with_name(name)
+            .with_position(line_offset, column_offset)
+            .with_source_map(source_map))
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "from_cells", signature=(cells, *, cell_name="Cell [{}]", start=1, name="<notebook>"))]
+    fn py_from_cells(
+        cells: Vec<String>,
+        cell_name: &str,
+        start: usize,
+        name: &str,
+    ) -> PyResult<Self> {
+        // Spans are located in the cells, so there must be at least one
+        if cells.is_empty() {
+            let msg = "A notebook must have at least one cell";
+            return Err(PyValueError::new_err(msg));
+        }
+        let cells = cells
+            .iter()
+            .enumerate()
+            .map(|(i, text)| {
+                let name = cell_name.replace("{}", &(start + i).to_string());
+                (name.into(), text.as_str())
+            })
+            .collect();
+        Ok(Source::from_cells(name.into(), cells))
+    }
+
+    #[getter]
+    fn cells(&self) -> Option<Vec<Source>> {
+        self.cells.as_ref().map(|cells| cells.sources.clone())
+    }
+
+    #[getter]
+    fn path(&self) -> &str {
+        &self.path
+    }
+
+    #[getter]
+    fn text(&self) -> &str {
+        &self.text
+    }
+
+    #[getter]
+    fn source_map(&self) -> Option<SourceMap> {
+        self.source_map.as_deref().cloned()
+    }
+
+    #[getter]
+    fn line_offset(&self) -> usize {
+        self.line_offset
+    }
+
+    #[getter]
+    fn column_offset(&self) -> usize {
+        self.column_offset
+    }
+
+    fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<(Bound<'py, PyAny>, (Bound<'py, PyTuple>,))> {
+        let constructor = py.get_type::<Source>().getattr("_from_state")?;
+        Ok((constructor, (self.state(py)?,)))
+    }
+
+    #[staticmethod]
+    fn _from_state(state: &Bound<'_, PyTuple>) -> PyResult<Self> {
+        Source::from_state(state)
+    }
+
+    fn __repr__(&self) -> String {
+        let mut args = vec![format!("path={:?}", self.path)];
+        if self.line_offset != 0 {
+            args.push(format!("line_offset={}", self.line_offset));
+        }
+        if self.column_offset != 0 {
+            args.push(format!("column_offset={}", self.column_offset));
+        }
+        format!("Source({})", args.join(", "))
+    }
+}
+
+impl Source {
+    /// State used for pickling: the path, the normalized text, the offset
+    /// map, the position, the cells and the source map.
+    fn state<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyTuple>> {
+        let offsets = match &self.offsets {
+            Some(offsets) => {
+                let removed: Vec<_> = offsets
+                    .removed
+                    .iter()
+                    .map(|r| (r.char, r.byte, r.len))
+                    .collect();
+                let bytes = match &offsets.bytes {
+                    ByteOffsets::Utf8 => "utf-8".into_pyobject(py)?.into_any(),
+                    ByteOffsets::SingleByte => "single-byte".into_pyobject(py)?.into_any(),
+                    ByteOffsets::Table(starts) => starts.into_pyobject(py)?,
+                };
+                Some((removed, bytes))
+            }
+            None => None,
+        };
+        let cells = match &self.cells {
+            Some(cells) => {
+                let spans = cells.spans.iter().map(|span| {
+                    let chars = (span.chars.start, span.chars.end);
+                    (chars, (span.bytes.start, span.bytes.end))
+                });
+                let cells = cells.sources.iter().cloned().zip(spans);
+                Some(
+                    cells
+                        .map(|(cell, (chars, bytes))| (cell, chars, bytes))
+                        .collect::<Vec<_>>(),
+                )
+            }
+            None => None,
+        };
+        let state = (
+            &*self.path,
+            self.py_text
+                .get_or_init(py, || PyString::new(py, &self.text).unbind()),
+            offsets,
+            (self.line_offset, self.column_offset),
+            self.virtual_path,
+            cells,
+            self.source_map.as_deref().cloned(),
+        );
+        state.into_pyobject(py)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn from_state(state: &Bound<'_, PyTuple>) -> PyResult<Self> {
+        let (path, text, offsets, position, virtual_path, cells, source_map): (
+            String,
+            Bound<'_, PyString>,
+            Option<(Vec<(usize, usize, usize)>, Bound<'_, PyAny>)>,
+            (usize, usize),
+            bool,
+            Option<Vec<(Source, (usize, usize), (usize, usize))>>,
+            Option<SourceMap>,
+        ) = state.extract()?;
+        let offsets = match offsets {
+            Some((removed, bytes)) => {
+                let removed =
+                    removed
+                        .into_iter()
+                        .map(|(char, byte, len)| Removed { char, byte, len });
+                let bytes = match bytes.extract::<String>().as_deref() {
+                    Ok("utf-8") => ByteOffsets::Utf8,
+                    Ok("single-byte") => ByteOffsets::SingleByte,
+                    _ => ByteOffsets::Table(bytes.extract()?),
+                };
+                Some(Arc::new(OffsetMap {
+                    removed: removed.collect(),
+                    bytes,
+                }))
+            }
+            None => None,
+        };
+        let cells = cells.map(|cells| {
+            let (sources, spans) = cells
+                .into_iter()
+                .map(|(cell, chars, bytes)| {
+                    let span = CellSpan {
+                        chars: chars.0..chars.1,
+                        bytes: bytes.0..bytes.1,
+                    };
+                    (cell, span)
+                })
+                .unzip();
+            Arc::new(Cells { sources, spans })
+        });
+        let py_text = GILOnceCell::new();
+        let _ = py_text.set(text.py(), text.clone().unbind());
+        Ok(Source {
+            path: path.into(),
+            text: text.to_str()?.into(),
+            py_text: Arc::new(py_text),
+            offsets,
+            line_offset: position.0,
+            column_offset: position.1,
+            virtual_path,
+            cells,
+            source_map: source_map.map(Arc::new),
+        })
+    }
+}
+
+/// The cells of a notebook.
+struct Cells {
+    sources: Vec<Source>,
+    spans: Vec<CellSpan>,
+}
+
+/// Global offsets of a cell, as chars and as bytes.
+struct CellSpan {
+    chars: Range<usize>,
+    bytes: Range<usize>,
+}
+
+impl Cells {
+    fn locate(&self, span: Range<usize>, byte_indexed: bool) -> _Span {
+        let global = |cell: &CellSpan| match byte_indexed {
+            true => cell.bytes.clone(),
+            false => cell.chars.clone(),
+        };
+        let idx = self
+            .spans
+            .partition_point(|cell| global(cell).start <= span.start)
+            .saturating_sub(1);
+        let cell = global(&self.spans[idx]);
+
+        // Spans crossing the cell boundary are truncated to the first cell
+        let start = span.start.clamp(cell.start, cell.end) - cell.start;
+        let end = span.end.clamp(cell.start, cell.end) - cell.start;
+        self.sources[idx].locate(start..end.max(start), byte_indexed)
+    }
+}
+
+/// How bytes are decoded into text.
+pub struct Decoding<'a> {
+    encoding: &'a str,
+    errors: &'a str,
+}
+
+impl<'a> Decoding<'a> {
+    pub fn new(encoding: &'a str, errors: &'a str) -> Self {
+        Decoding { encoding, errors }
+    }
+
+    pub fn is_utf8(&self) -> bool {
+        let name = self.encoding.to_lowercase().replace('_', "-");
+        matches!(name.as_str(), "utf-8" | "utf8" | "u8" | "utf-8-sig")
+    }
+
+    /// Encode text back to bytes, e.g., to write an edited file.
+    pub fn encode(&self, text: &str) -> PyResult<Vec<u8>> {
+        if self.is_utf8() {
+            return Ok(text.as_bytes().to_vec());
+        }
+        Python::with_gil(|py| {
+            PyString::new(py, text)
+                .call_method1("encode", (self.encoding, self.errors))?
+                .extract()
+        })
+    }
+
+    /// Decode using Python codecs, keeping track of where each char starts in
+    /// the original bytes.
+    fn decode(&self, data: &[u8]) -> PyResult<(String, ByteOffsets)> {
+        Python::with_gil(|py| {
+            let text: String = PyBytes::new(py, data)
+                .call_method1("decode", (self.encoding, self.errors))?
+                .extract()?;
+            // Single byte encodings such as latin-1 map bytes to chars 1:1,
+            // unless the error policy replaced or dropped some of them
+            if text.chars().count() == data.len() && self.is_single_byte(py)? {
+                return Ok((text, ByteOffsets::SingleByte));
+            }
+            // Otherwise the incremental decoder is fed one byte at a time, so
+            // we know where each char starts
+            let codecs = PyModule::import(py, "codecs")?;
+            let decoder = codecs
+                .call_method1("getincrementaldecoder", (self.encoding,))?
+                .call1((self.errors,))?;
+            let mut text = String::with_capacity(data.len());
+            let mut starts = Vec::with_capacity(data.len() + 1);
+            let mut pending = 0;
+            for i in 0..=data.len() {
+                let chunk = match data.get(i) {
+                    Some(byte) => decoder.call_method1("decode", (PyBytes::new(py, &[*byte]),))?,
+                    None => decoder.call_method1("decode", (PyBytes::new(py, b""), true))?,
+                };
+                let chunk = chunk.downcast::<PyString>()?.to_str()?;
+                for (k, c) in chunk.chars().enumerate() {
+                    starts.push(if k == 0 { pending } else { i });
+                    text.push(c);
+                }
+                if !chunk.is_empty() {
+                    pending = i + 1;
+                }
+            }
+            starts.push(data.len());
+            Ok((text, ByteOffsets::Table(starts)))
+        })
+    }
+
+    /// Whether the codec decodes every byte to exactly one char, like latin-1,
+    /// ascii and the charmap codecs.
+    fn is_single_byte(&self, py: Python<'_>) -> PyResult<bool> {
+        let info = PyModule::import(py, "codecs")?.call_method1("lookup", (self.encoding,))?;
+        let name: String = info.getattr("name")?.extract()?;
+        if name == "iso8859-1" || name == "ascii" {
+            return Ok(true);
+        }
+        let module = info.getattr("incrementaldecoder")?.getattr("__module__")?;
+        match PyModule::import(py, "sys")?
+            .getattr("modules")?
+            .get_item(module)
+        {
+            Ok(module) => module.hasattr("decoding_table"),
+            Err(_) => Ok(false),
+        }
+    }
+}
+
+/// A file decoded without normalization, so it can be edited in place.
+pub struct RawText {
+    pub(crate) text: String,
+    bytes: ByteOffsets,
+}
+
+impl RawText {
+    pub fn decode(data: &[u8], decoding: &Decoding) -> PyResult<Self> {
+        if decoding.is_utf8() {
+            if let Ok(text) = std::str::from_utf8(data) {
+                let text = text.to_string();
+                return Ok(RawText {
+                    text,
+                    bytes: ByteOffsets::Utf8,
+                });
+            }
+        }
+        let (text, bytes) = decoding.decode(data)?;
+        Ok(RawText { text, bytes })
+    }
+
+    /// Byte position in the decoded text of an offset in the original input.
+    ///
+    /// Returns None if the offset is out of bounds or falls inside a char.
+    pub fn offset(&self, offset: usize, byte_indexed: bool) -> Option<usize> {
+        let char = match (&self.bytes, byte_indexed) {
+            (_, false) | (ByteOffsets::SingleByte, true) => offset,
+            (ByteOffsets::Utf8, true) => {
+                return self.text.is_char_boundary(offset).then_some(offset);
+            }
+            (ByteOffsets::Table(starts), true) => starts.binary_search(&offset).ok()?,
+        };
+        match self.text.char_indices().nth(char) {
+            Some((byte, _)) => Some(byte),
+            None => (char == self.text.chars().count()).then_some(self.text.len()),
+        }
+    }
+}
+
+impl Default for Decoding<'_> {
+    fn default() -> Self {
+        Decoding::new("utf-8", "strict")
+    }
+}
+
+/// Relation between byte offsets in the original input and chars.
+enum ByteOffsets {
+    /// The input was UTF-8 encoded.
+    Utf8,
+    /// Each byte of the input is a single char.
+    SingleByte,
+    /// Offset in the original bytes where each char starts.
+    Table(Vec<usize>),
+}
+
+/// A char removed from the original text during normalization.
+struct Removed {
+    char: usize,
+    byte: usize,
+    len: usize,
+}
+
+/// Maps offsets in the original input to offsets in the normalized text.
+struct OffsetMap {
+    removed: Vec<Removed>,
+    bytes: ByteOffsets,
+}
+
+impl OffsetMap {
+    fn translate(&self, text: &str, offset: usize, byte_indexed: bool) -> usize {
+        if !byte_indexed {
+            return self.char_offset(offset);
+        }
+        match &self.bytes {
+            ByteOffsets::Utf8 => {
+                let removed: usize = self
+                    .removed
+                    .iter()
+                    .take_while(|r| r.byte < offset)
+                    .map(|r| r.len.min(offset - r.byte))
+                    .sum();
+                offset - removed
+            }
+            ByteOffsets::SingleByte => char_to_byte(text, self.char_offset(offset)),
+            ByteOffsets::Table(starts) => {
+                let char = starts.partition_point(|&start| start < offset);
+                char_to_byte(text, self.char_offset(char))
+            }
+        }
+    }
+
+    /// Inverse of `translate` for a byte offset in the normalized text.
+    ///
+    /// The offset points to the char in the original input, after any removed
+    /// chars that precede it.
+    fn original(&self, text: &str, offset: usize, byte_indexed: bool) -> usize {
+        let mut char = text[..offset].chars().count();
+        for removed in &self.removed {
+            if removed.char <= char {
+                char += 1;
+            }
+        }
+        if !byte_indexed {
+            return char;
+        }
+        match &self.bytes {
+            ByteOffsets::Utf8 => {
+                let mut byte = offset;
+                for removed in &self.removed {
+                    if removed.byte <= byte {
+                        byte += removed.len;
+                    }
+                }
+                byte
+            }
+            ByteOffsets::SingleByte => char,
+            ByteOffsets::Table(starts) => starts[char],
+        }
+    }
+
+    /// Like `original`, but for the end of a span: the offset points right
+    /// after the previous char, before any removed chars.
+    fn original_end(&self, text: &str, offset: usize, byte_indexed: bool) -> usize {
+        let Some(c) = text[..offset].chars().next_back() else {
+            return 0;
+        };
+        let last = offset - c.len_utf8();
+        match (&self.bytes, byte_indexed) {
+            (_, false) | (ByteOffsets::SingleByte, true) => {
+                self.original(text, last, byte_indexed) + 1
+            }
+            (ByteOffsets::Utf8, true) => self.original(text, last, true) + c.len_utf8(),
+            (ByteOffsets::Table(starts), true) => starts[self.original(text, last, false) + 1],
+        }
+    }
+
+    fn char_offset(&self, offset: usize) -> usize {
+        offset - self.removed.partition_point(|r| r.char < offset)
+    }
+}
+
+pub fn floor_char_boundary(text: &str, mut offset: usize) -> usize {
+    if offset >= text.len() {
+        return offset;
+    }
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+pub fn char_to_byte(text: &str, offset: usize) -> usize {
+    text.char_indices()
+        .nth(offset)
+        .map_or(text.len(), |(byte, _)| byte)
+}
+
+/// Strip the BOM and replace CRLF line endings by LF.
+fn normalize(text: &str, bytes: ByteOffsets) -> (Arc<str>, Option<OffsetMap>) {
+    if !text.starts_with('\u{feff}') && !text.contains('\r') {
+        return match bytes {
+            ByteOffsets::Utf8 => (text.into(), None),
+            bytes => {
+                let removed = Vec::new();
+                (text.into(), Some(OffsetMap { removed, bytes }))
+            }
+        };
+    }
+
+    let mut removed = Vec::new();
+    let mut normalized = String::with_capacity(text.len());
+    for (char, (byte, c)) in text.char_indices().enumerate() {
+        let is_bom = char == 0 && c == '\u{feff}';
+        let is_crlf = c == '\r' && text[byte + 1..].starts_with('\n');
+        if is_bom || is_crlf {
+            let len = c.len_utf8();
+            removed.push(Removed { char, byte, len });
+        } else {
+            normalized.push(c);
+        }
+    }
+    (normalized.into(), Some(OffsetMap { removed, bytes }))
+}
+
+fn from_path(obj: &Bound<'_, PyAny>) -> PyResult<Arc<str>> {
+    Python::with_gil(|py| {
+        let path_type = PyModule::import(py, "pathlib")?.getattr("Path")?;
+        if obj.is_instance(&path_type)? {
+            Ok(obj.to_string().into())
+        } else {
+            Err(PyTypeError::new_err("Expected a Path object or a string"))
+        }
+    })
+}
+
+pub fn from_path_or_str(obj: &Bound<'_, PyAny>) -> PyResult<Arc<str>> {
+    // Strings
+    if let Ok(path) = obj.downcast::<PyString>() {
+        Ok(path.to_str()?.into())
+
+    // Path objects
+    } else if let Ok(path) = from_path(obj) {
+        Ok(path)
+    } else {
+        let msg = "Expected a string or Path object";
+        Err(PyTypeError::new_err(msg))
+    }
+}
+
+/// Sources captured from non-seekable streams, indexed by the stream object.
+static STREAMS: GILOnceCell<PyObject> = GILOnceCell::new();
+
+fn from_file_like(obj: &Bound<'_, PyAny>, decoding: &Decoding) -> PyResult<Source> {
+    let seekable = obj
+        .call_method0("seekable")
+        .and_then(|seekable| seekable.is_truthy())
+        .unwrap_or(false);
+
+    // Non-seekable streams such as stdin can only be read once, so we keep
+    // the captured text around for as long as the stream is alive.
+    let streams = STREAMS.get_or_try_init(obj.py(), || {
+        let weakref = PyModule::import(obj.py(), "weakref")?;
+        Ok::<_, PyErr>(weakref.call_method0("WeakKeyDictionary")?.unbind())
+    })?;
+    let streams = streams.bind(obj.py());
+    if !seekable {
+        if let Ok(source) = streams.get_item(obj) {
+            return Ok(source.downcast::<Source>()?.get().clone());
+        }
+    }
+
+    // Seekable streams are rewound, so they can be read again by other reports
+    let position = match seekable {
+        true => Some(obj.call_method0("tell")?),
+        false => None,
+    };
+    let data = obj.call_method0("read")?;
+    if let Some(position) = position {
+        obj.call_method1("seek", (position,))?;
+    }
+
+    let path: Arc<str> = match obj.getattr("name") {
+        Ok(value) => match value.downcast::<PyString>() {
+            Ok(name) => name.to_str()?.into(),
+            // Files opened from a file descriptor have an int name
+            Err(_) => "<string>".into(),
+        },
+        Err(_) => "<string>".into(),
+    };
+    let source = Source::from_data(path, &data, decoding)?;
+    if !seekable {
+        // Streams that do not support weak references are simply not cached
+        let _ = streams.set_item(obj, Py::new(obj.py(), source.clone())?);
+    }
+    Ok(source)
+}
@@ -0,0 +1,109 @@
+use crate::source::from_path_or_str;
+use pyo3::{exceptions::PyValueError, prelude::*};
+use std::{ops::Range, sync::Arc};
+
+/// Maps ranges of generated code back to the original files.
+#[pyclass(module = "theseus")]
+#[derive(Clone, Debug, Default)]
+pub struct SourceMap {
+    pub(crate) mappings: Vec<Mapping>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Mapping {
+    generated: Range<usize>,
+    path: Arc<str>,
+    original: Range<usize>,
+}
+
+impl SourceMap {
+    /// Find the original location of a span of generated code.
+    ///
+    /// If mappings are nested, the smallest generated range that contains
+    /// the whole span is used. Spans are mapped offset by offset when both
+    /// ranges have the same length, otherwise they cover the whole original
+    /// range.
+    pub fn map(&self, span: &Range<usize>) -> Option<(Arc<str>, Range<usize>)> {
+        let mapping = self
+            .mappings
+            .iter()
+            .filter(|m| m.generated.start <= span.start && span.end <= m.generated.end)
+            .min_by_key(|m| m.generated.len())?;
+
+        let original = if mapping.generated.len() == mapping.original.len() {
+            let start = mapping.original.start + (span.start - mapping.generated.start);
+            start..start + span.len()
+        } else {
+            mapping.original.clone()
+        };
+        Some((mapping.path.clone(), original))
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &Arc<str>> {
+        self.mappings.iter().map(|m| &m.path)
+    }
+}
+
+#[pymethods]
+impl SourceMap {
+    #[new]
+    #[pyo3(signature=(mappings=vec![]))]
+    fn py_new(mappings: Vec<(usize, usize, Bound<'_, PyAny>, usize, usize)>) -> PyResult<Self> {
+        let mut map = SourceMap::default();
+        for (start, end, path, original_start, original_end) in mappings {
+            map.add(start, end, &path, original_start, original_end)?;
+        }
+        Ok(map)
+    }
+
+    fn add(
+        &mut self,
+        start: usize,
+        end: usize,
+        path: &Bound<'_, PyAny>,
+        original_start: usize,
+        original_end: usize,
+    ) -> PyResult<()> {
+        if start > end || original_start > original_end {
+            let msg = "Start index must be less than or equal to end index";
+            return Err(PyValueError::new_err(msg));
+        }
+        self.mappings.push(Mapping {
+            generated: start..end,
+            path: from_path_or_str(path)?,
+            original: original_start..original_end,
+        });
+        Ok(())
+    }
+
+    #[getter]
+    fn mappings(&self) -> Vec<(usize, usize, String, usize, usize)> {
+        self.mappings
+            .iter()
+            .map(|m| {
+                let (generated, original) = (&m.generated, &m.original);
+                let path = m.path.to_string();
+                (
+                    generated.start,
+                    generated.end,
+                    path,
+                    original.start,
+                    original.end,
+                )
+            })
+            .collect()
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn __getnewargs__(&self) -> (Vec<(usize, usize, String, usize, usize)>,) {
+        (self.mappings(),)
+    }
+
+    fn __len__(&self) -> usize {
+        self.mappings.len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("SourceMap({:?})", self.mappings())
+    }
+}
@@ -0,0 +1,164 @@
+use crate::source::Source;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::sync::GILOnceCell;
+use pyo3::types::{PyDict, PyTuple, PyType};
+use std::ops::Range;
+
+/// Adapters registered with `register_span_adapter`, indexed by type.
+static ADAPTERS: GILOnceCell<Py<PyDict>> = GILOnceCell::new();
+
+/// A span given either as a pair of offsets or as an object implementing the
+/// span protocol.
+///
+/// Objects may report their offsets in bytes or chars, which are converted
+/// to the units of the report once the source is known.
+pub struct SpanArg {
+    pub(crate) range: Range<usize>,
+    pub(crate) bytes: Option<bool>,
+}
+
+impl SpanArg {
+    pub fn from_params(start: &Bound<'_, PyAny>, end: Option<usize>) -> PyResult<Self> {
+        if let Ok(start) = start.extract::<usize>() {
+            let Some(end) = end else {
+                let msg = "end is required when start is an offset";
+                return Err(PyTypeError::new_err(msg));
+            };
+            return Ok(SpanArg {
+                range: start..end,
+                bytes: None,
+            });
+        }
+        if end.is_some() {
+            let msg = "end cannot be given together with a span object";
+            return Err(PyTypeError::new_err(msg));
+        }
+        Self::from_object(start)
+    }
+
+    /// Span of an object following the span protocol, or of a
+    /// (start, end[, unit]) tuple.
+    pub fn from_object(obj: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let span = match obj.downcast::<PyTuple>() {
+            Ok(span) => span.clone(),
+            Err(_) => span_of(obj)?,
+        };
+        let (range, bytes) = match span.len() {
+            2 => (span.extract::<(usize, usize)>()?, None),
+            3 => {
+                let (start, end, unit): (usize, usize, String) = span.extract()?;
+                let bytes = match unit.as_str() {
+                    "bytes" => true,
+                    "chars" => false,
+                    _ => {
+                        let msg =
+                            format!("Invalid span unit: {unit:?}, expected 'chars' or 'bytes'");
+                        return Err(PyValueError::new_err(msg));
+                    }
+                };
+                ((start, end), Some(bytes))
+            }
+            _ => {
+                let msg = "Spans must be (start, end) or (start, end, unit) tuples";
+                return Err(PyTypeError::new_err(msg));
+            }
+        };
+        Ok(SpanArg {
+            range: range.0..range.1,
+            bytes,
+        })
+    }
+
+    /// Offsets in the units used by the report.
+    pub fn resolve(self, source: Option<&Source>, byte_indexed: bool) -> Range<usize> {
+        match (self.bytes, source) {
+            (Some(bytes), Some(source)) => source.convert_span(self.range, bytes, byte_indexed),
+            _ => self.range,
+        }
+    }
+}
+
+/// Call the span protocol, a registered adapter or a built-in adapter.
+fn span_of<'py>(obj: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyTuple>> {
+    let py = obj.py();
+    let span = if obj.hasattr("__theseus_span__")? {
+        obj.call_method0("__theseus_span__")?
+    } else if let Some(adapter) = registered_adapter(obj)? {
+        adapter.call1((obj,))?
+    } else {
+        builtin_span(obj)?.into_pyobject(py)?.into_any()
+    };
+    Ok(span.downcast_into::<PyTuple>()?)
+}
+
+fn registered_adapter<'py>(obj: &Bound<'py, PyAny>) -> PyResult<Option<Bound<'py, PyAny>>> {
+    let Some(adapters) = ADAPTERS.get(obj.py()) else {
+        return Ok(None);
+    };
+    let adapters = adapters.bind(obj.py());
+    for cls in obj.get_type().mro() {
+        if let Some(adapter) = adapters.get_item(cls)? {
+            return Ok(Some(adapter));
+        }
+    }
+    Ok(None)
+}
+
+/// Adapters for common parser libraries. Types are recognized by their
+/// module name, so the libraries are never imported.
+fn builtin_span(obj: &Bound<'_, PyAny>) -> PyResult<(usize, usize, &'static str)> {
+    let module: String = obj.get_type().getattr("__module__")?.extract()?;
+    match module.split('.').next().unwrap_or_default() {
+        // Nodes have byte offsets into the parsed buffer
+        "tree_sitter" if obj.hasattr("start_byte")? => Ok((
+            obj.getattr("start_byte")?.extract()?,
+            obj.getattr("end_byte")?.extract()?,
+            "bytes",
+        )),
+        // Tokens have positions, trees only when parsed with propagate_positions
+        "lark" => {
+            let pos = match obj.hasattr("start_pos")? {
+                true => obj.clone(),
+                false => obj.getattr("meta")?,
+            };
+            let (start, end) = (pos.getattr("start_pos"), pos.getattr("end_pos"));
+            match (start, end) {
+                (Ok(start), Ok(end)) => Ok((start.extract()?, end.extract()?, "chars")),
+                _ => {
+                    let msg = "Lark tree has no position, parse with propagate_positions=True";
+                    Err(PyValueError::new_err(msg))
+                }
+            }
+        }
+        "sly" if obj.hasattr("index")? => {
+            let start: usize = obj.getattr("index")?.extract()?;
+            let end = match obj.getattr("end") {
+                Ok(end) => end.extract()?,
+                Err(_) => start + obj.getattr("value")?.str()?.to_str()?.chars().count(),
+            };
+            Ok((start, end, "chars"))
+        }
+        // Parse exceptions only know where the parser stopped
+        "pyparsing" if obj.hasattr("loc")? => {
+            let loc: usize = obj.getattr("loc")?.extract()?;
+            Ok((loc, loc, "chars"))
+        }
+        _ => {
+            let name = obj.get_type().name()?;
+            let msg = format!("Expected an offset or an object with a span, got {name}");
+            Err(PyTypeError::new_err(msg))
+        }
+    }
+}
+
+/// Register a function that returns the span of instances of cls.
+#[pyfunction]
+pub fn register_span_adapter(cls: &Bound<'_, PyType>, adapter: &Bound<'_, PyAny>) -> PyResult<()> {
+    if !adapter.is_callable() {
+        let msg = "Span adapters must be callable";
+        return Err(PyTypeError::new_err(msg));
+    }
+    let adapters = ADAPTERS.get_or_init(cls.py(), || PyDict::new(cls.py()).unbind());
+    adapters.bind(cls.py()).set_item(cls, adapter)
+}
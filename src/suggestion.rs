@@ -0,0 +1,242 @@
+use crate::{_Span, cache::SourceCache, config::Config, source::char_to_byte};
+use ariadne::{Color, Fmt};
+use pyo3::{
+    exceptions::PyValueError,
+    prelude::*,
+    types::{PyDict, PyTuple},
+};
+use std::{io::Write, ops::Range, sync::Arc};
+
+// The margin and help colors match those of ariadne
+const REMOVED: Color = Color::Red;
+const INSERTED: Color = Color::Green;
+const HELP: Color = Color::Fixed(115);
+const MARGIN: Color = Color::Fixed(246);
+// ariadne only sets colors, so removed text is struck through with SGR 9,
+// which is cleared by the reset after the colored text
+const STRIKE: &str = "\x1b[9m";
+
+/// A fix-it suggestion replacing a span of code.
+#[pyclass(module = "theseus", frozen, eq, hash)]
+#[derive(Clone, Debug, PartialEq, Hash)]
+pub struct Suggestion {
+    pub(crate) span: Range<usize>,
+    pub(crate) target: Option<Arc<str>>,
+    pub(crate) replacement: String,
+    pub(crate) message: Option<String>,
+    pub(crate) applicability: Applicability,
+}
+
+/// How confident the tool is that a suggestion can be applied automatically.
+#[derive(Clone, Copy, Debug, PartialEq, Hash)]
+pub enum Applicability {
+    MachineApplicable,
+    MaybeIncorrect,
+    HasPlaceholders,
+    Unspecified,
+}
+
+impl Applicability {
+    fn from_str(name: &str) -> PyResult<Self> {
+        match name {
+            "machine-applicable" => Ok(Applicability::MachineApplicable),
+            "maybe-incorrect" => Ok(Applicability::MaybeIncorrect),
+            "has-placeholders" => Ok(Applicability::HasPlaceholders),
+            "unspecified" => Ok(Applicability::Unspecified),
+            _ => {
+                let msg = format!("Unknown applicability: {}", name);
+                Err(PyValueError::new_err(msg))
+            }
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Applicability::MachineApplicable => "machine-applicable",
+            Applicability::MaybeIncorrect => "maybe-incorrect",
+            Applicability::HasPlaceholders => "has-placeholders",
+            Applicability::Unspecified => "unspecified",
+        }
+    }
+}
+
+impl Suggestion {
+    /// Render the patched lines below a report.
+    ///
+    /// With colors, removed text is struck through in red and inserted text
+    /// is shown in green, in place. Without colors, the old and new lines are
+    /// shown as a diff.
+    pub fn write(
+        &self,
+        w: &mut dyn Write,
+        span: &_Span,
+        cache: &SourceCache,
+        config: &Config,
+    ) -> std::io::Result<()> {
+        let Some(text) = cache.text(&span.0) else {
+            return Ok(());
+        };
+        let (start, end) = match config.byte_indexed {
+            true => (span.1.start.min(text.len()), span.1.end.min(text.len())),
+            false => (
+                char_to_byte(text, span.1.start),
+                char_to_byte(text, span.1.end),
+            ),
+        };
+        let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = text[end..].find('\n').map_or(text.len(), |i| end + i);
+        let (before, removed, after) = (
+            &text[line_start..start],
+            &text[start..end],
+            &text[end..line_end],
+        );
+        let first_line = text[..line_start].matches('\n').count() + 1;
+
+        let old_lines = format!("{}{}{}", before, removed, after);
+        let new_lines = format!("{}{}{}", before, self.replacement, after);
+        let line_count = old_lines
+            .matches('\n')
+            .count()
+            .max(new_lines.matches('\n').count());
+        let width = (first_line + line_count).to_string().len();
+        let (vbar, ltop, hbar, lbot) = match config.ascii {
+            true => ('|', ',', '-', '\''),
+            false => ('│', '╭', '─', '╯'),
+        };
+
+        let paint = |text: &str, color: Color| match config.color {
+            true => paint_lines(text, color, ""),
+            false => text.to_string(),
+        };
+        let margin = |line: Option<usize>| {
+            let number = line.map_or(String::new(), |n| n.to_string());
+            paint(&format!(" {:>width$} {}", number, vbar), MARGIN)
+        };
+
+        let message = self.message.as_deref().unwrap_or("try this");
+        writeln!(w, "{} {}", paint("Help:", HELP), message)?;
+        let location = cache.location(span, config.byte_indexed);
+        let top = paint(&format!("{}{}", ltop, hbar), MARGIN);
+        writeln!(w, "{}{}[ {} ]", " ".repeat(width + 2), top, location)?;
+        writeln!(w, "{}", margin(None))?;
+        if config.color {
+            let removed = paint_lines(removed, REMOVED, STRIKE);
+            let inserted = paint_lines(&self.replacement, INSERTED, "");
+            let patched = format!("{}{}{}{}", before, removed, inserted, after);
+            for (i, line) in patched.split('\n').enumerate() {
+                writeln!(w, "{} {}", margin(Some(first_line + i)), line)?;
+            }
+        } else {
+            if !removed.is_empty() {
+                for (i, line) in old_lines.split('\n').enumerate() {
+                    writeln!(w, "{} - {}", margin(Some(first_line + i)), line)?;
+                }
+            }
+            for (i, line) in new_lines.split('\n').enumerate() {
+                writeln!(w, "{} + {}", margin(Some(first_line + i)), line)?;
+            }
+        }
+        let bottom = format!("{}{}", hbar.to_string().repeat(width + 2), lbot);
+        writeln!(w, "{}", paint(&bottom, MARGIN))
+    }
+}
+
+/// Color each line of the text separately, so margins are not colored,
+/// after the given SGR attributes.
+fn paint_lines(text: &str, color: Color, attrs: &str) -> String {
+    let lines = text.split('\n').map(|line| match line.is_empty() {
+        true => String::new(),
+        false => format!("{}{}", attrs, line.fg(color)),
+    });
+    lines.collect::<Vec<_>>().join("\n")
+}
+
+#[pymethods]
+impl Suggestion {
+    #[new]
+    #[pyo3(signature=(start, end, replacement, *, path=None, message=None, applicability="unspecified"))]
+    pub(crate) fn py_new(
+        start: usize,
+        end: usize,
+        replacement: String,
+        path: Option<&str>,
+        message: Option<String>,
+        applicability: &str,
+    ) -> PyResult<Self> {
+        if start > end {
+            let msg = "Start index must be less than or equal to end index";
+            return Err(PyValueError::new_err(msg));
+        }
+        Ok(Suggestion {
+            span: start..end,
+            target: path.map(Arc::from),
+            replacement,
+            message,
+            applicability: Applicability::from_str(applicability)?,
+        })
+    }
+
+    #[getter]
+    fn start(&self) -> usize {
+        self.span.start
+    }
+
+    #[getter]
+    fn end(&self) -> usize {
+        self.span.end
+    }
+
+    #[getter]
+    fn path(&self) -> Option<&str> {
+        self.target.as_deref()
+    }
+
+    #[getter]
+    fn replacement(&self) -> &str {
+        &self.replacement
+    }
+
+    #[getter]
+    fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    #[getter]
+    fn applicability(&self) -> &'static str {
+        self.applicability.as_str()
+    }
+
+    fn __getnewargs_ex__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<(Bound<'py, PyTuple>, Bound<'py, PyDict>)> {
+        let args = (self.span.start, self.span.end, &self.replacement).into_pyobject(py)?;
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("path", self.target.as_deref())?;
+        kwargs.set_item("message", self.message.as_deref())?;
+        kwargs.set_item("applicability", self.applicability.as_str())?;
+        Ok((args, kwargs))
+    }
+
+    fn __repr__(&self) -> String {
+        let mut args = vec![
+            self.span.start.to_string(),
+            self.span.end.to_string(),
+            format!("{:?}", self.replacement),
+        ];
+        if let Some(target) = &self.target {
+            args.push(format!("path={target:?}"));
+        }
+        if let Some(message) = &self.message {
+            args.push(format!("message={message:?}"));
+        }
+        if self.applicability != Applicability::Unspecified {
+            args.push(format!("applicability={:?}", self.applicability.as_str()));
+        }
+        format!("Suggestion({})", args.join(", "))
+    }
+
+    fn __str__(&self) -> String {
+        self.__repr__()
+    }
+}
@@ -0,0 +1,98 @@
+use crate::report::{Report, ReportKind};
+use pyo3::exceptions::{PyTypeError, PyUserWarning, PyWarning};
+use pyo3::prelude::*;
+use pyo3::sync::GILOnceCell;
+use pyo3::types::{PyDict, PyType};
+
+/// Registries used by warn_explicit to show "default" and "module" warnings
+/// only once per location, one per file like the registries of modules.
+static REGISTRIES: GILOnceCell<Py<PyDict>> = GILOnceCell::new();
+
+/// Emit a warning for a report through the warnings module.
+///
+/// The warning points to the file and line of the report, so -W filters and
+/// escalation to errors work as usual. The report is attached to the warning
+/// as its `report` attribute.
+#[pyfunction]
+#[pyo3(signature=(report, category=None))]
+pub fn warn(report: &Bound<'_, Report>, category: Option<&Bound<'_, PyAny>>) -> PyResult<()> {
+    let py = report.py();
+    let category = match category {
+        Some(category) => category.clone(),
+        None => py.get_type::<PyUserWarning>().into_any(),
+    };
+    let base = py.get_type::<PyWarning>();
+    let is_warning = category
+        .downcast::<PyType>()
+        .map(|category| category.is_subclass(&base))
+        .unwrap_or(Ok(false))?;
+    if !is_warning {
+        let msg = "category must be a Warning subclass";
+        return Err(PyTypeError::new_err(msg));
+    }
+
+    let (path, line, text) = {
+        let report = report.borrow();
+        let (path, line) = report.start_line()?;
+        (path, line, report.summary())
+    };
+    let warning = category.call1((text,))?;
+    warning.setattr("report", report)?;
+    let registries = REGISTRIES
+        .get_or_init(py, || PyDict::new(py).unbind())
+        .bind(py);
+    // Registries are keyed by message, category and line, without the file
+    let registry = registries.call_method1("setdefault", (&*path, PyDict::new(py)))?;
+    let warnings = PyModule::import(py, "warnings")?;
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("registry", registry)?;
+    warnings.call_method(
+        "warn_explicit",
+        (&warning, category, &*path, line),
+        Some(&kwargs),
+    )?;
+    Ok(())
+}
+
+/// A replacement for warnings.showwarning that renders warnings emitted by
+/// `warn` as reports.
+#[pyfunction]
+#[pyo3(signature=(message, category, filename, lineno, file=None, line=None))]
+pub fn showwarning(
+    message: &Bound<'_, PyAny>,
+    category: &Bound<'_, PyAny>,
+    filename: &Bound<'_, PyAny>,
+    lineno: &Bound<'_, PyAny>,
+    file: Option<&Bound<'_, PyAny>>,
+    line: Option<&Bound<'_, PyAny>>,
+) -> PyResult<()> {
+    let py = message.py();
+    let file = match file {
+        Some(file) if !file.is_none() => file.clone(),
+        // Like the standard hook, warnings are lost without a stderr
+        _ => match PyModule::import(py, "sys")?.getattr("stderr")? {
+            stderr if stderr.is_none() => return Ok(()),
+            stderr => stderr,
+        },
+    };
+    let report = message
+        .getattr("report")
+        .ok()
+        .and_then(|report| report.downcast_into::<Report>().ok());
+    let text = match report {
+        Some(report) => {
+            let color = file
+                .call_method0("isatty")
+                .and_then(|tty| tty.is_truthy())
+                .unwrap_or(false);
+            report.borrow_mut().render_as(ReportKind::Warning, color)?
+        }
+        None => {
+            let warnings = PyModule::import(py, "warnings")?;
+            let args = (message, category, filename, lineno, line);
+            warnings.call_method1("formatwarning", args)?.extract()?
+        }
+    };
+    file.call_method1("write", (text,))?;
+    Ok(())
+}
@@ -1,9 +1,43 @@
+import ast
+import builtins
+import copy
 import io
-from contextlib import redirect_stdout
+import logging
+import pickle
+import sys
+import warnings
+import tokenize
+from contextlib import redirect_stderr, redirect_stdout
 from itertools import islice
 from pathlib import Path
 
-from theseus import Color, ColorGenerator, Config, Label, Report  # type: ignore
+import pytest
+
+from theseus import (  # type: ignore
+    Color,
+    ColorGenerator,
+    Config,
+    Expansion,
+    Label,
+    Report,
+    ReportError,
+    ReportHandler,
+    Source,
+    SourceMap,
+    Suggestion,
+    apply_edits,
+    install_excepthook,
+    register_exception_converter,
+    register_span_adapter,
+    showwarning,
+    warn,
+)
+
+
+def render(report: Report) -> str:
+    with redirect_stdout(io.StringIO()) as data:
+        report.print()
+    return data.getvalue()
 
 
 class TestColor:
@@ -68,6 +102,207 @@ class TestLabel:
         assert repr(label) == str(label)
 
 
+class TestConfig:
+    def paths(self, config: Config) -> str:
+        path = Path(__file__).parent / "example.lox"
+        report = Report(path, 0, 5, config=config)
+        report.label(0, 5)
+        with redirect_stdout(io.StringIO()) as data:
+            report.print()
+        return data.getvalue().splitlines()[1]
+
+    def test_path_display_policies(self):
+        tests = Path(__file__).parent
+        assert "example.lox:1:1" in self.paths(Config(color=False, path_display="basename"))
+        assert "[ example.lox:1:1 ]" in self.paths(Config(color=False, path_root=tests))
+        uri = self.paths(Config(color=False, path_display="uri"))
+        assert (tests / "example.lox").as_uri() in uri
+        full = self.paths(Config(color=False, path_display="full"))
+        assert str(tests / "example.lox") in full
+
+    def test_path_display_callable(self):
+        config = Config(color=False, path_display=lambda path: path.upper()[-11:])
+        assert "[ EXAMPLE.LOX:1:1 ]" in self.paths(config)
+
+    def test_path_display_does_not_change_pseudo_paths(self):
+        config = Config(color=False, path_display="uri")
+        report = Report("print(1)", 0, 5, config=config)
+        report.label(0, 5)
+        with redirect_stdout(io.StringIO()) as data:
+            report.print()
+        assert "[ <string>:1:1 ]" in data.getvalue()
+
+    def test_invalid_path_display(self):
+        with pytest.raises(ValueError):
+            Config(path_display="absolute")
+        with pytest.raises(ValueError):
+            Config(path_display="basename", path_root=".")
+
+
+class TestSource:
+    CODE = 'a = 1\r\nbb = "é"\r\n'
+
+    def render_label(self, source, start, end, byte_indexed=False) -> str:
+        config = Config(color=False, compact=True, byte_indexed=byte_indexed)
+        report = Report(source, start, end, config=config)
+        report.label(start, end, message="here")
+        return render(report)
+
+    def test_crlf_is_normalized(self):
+        source = Source(self.CODE)
+        assert source.text == 'a = 1\nbb = "é"\n'
+        output = self.render_label(source, 13, 14)
+        assert output.splitlines()[1:] == [
+            "   ╭─[ <string>:2:7 ]",
+            ' 2 │bb = "é"',
+            "   │      ╰─ here",
+        ]
+
+    def test_bom_and_crlf_with_byte_offsets(self):
+        data = b"\xef\xbb\xbf" + self.CODE.encode()
+        assert Source(data).text == 'a = 1\nbb = "é"\n'
+        output = self.render_label(data, 16, 18, byte_indexed=True)
+        assert "<string>:2:7" in output
+
+    def test_latin1_file(self, tmp_path):
+        path = tmp_path / "latin1.lox"
+        path.write_bytes(self.CODE.encode("latin-1"))
+        with pytest.raises(UnicodeDecodeError):
+            Source(path)
+
+        source = Source(path, encoding="latin-1")
+        assert source.path == str(path)
+        assert source.text == 'a = 1\nbb = "é"\n'
+        assert ":2:7" in self.render_label(source, 13, 14, byte_indexed=True)
+
+    def test_binary_file_like(self):
+        # Two bytes for the BOM and two bytes for each char
+        data = io.BytesIO(self.CODE.encode("utf-16"))
+        config = Config(color=False, byte_indexed=True)
+        report = Report(data, 28, 30, encoding="utf-16", config=config)
+        report.label(28, 30)
+        assert ":2:7" in render(report)
+
+    def test_name_overrides_path(self):
+        path = Path(__file__).parent / "example.lox"
+        assert Source(path, name="main.lox").path == "main.lox"
+        assert Source(io.StringIO("x = 1"), name="-").path == "-"
+
+        report = Report(io.StringIO("x = 1"), 0, 1, name="<stdin>")
+        report.label(0, 1)
+        assert "<stdin>:1:1" in render(report)
+
+    def test_non_seekable_stream_is_read_once(self):
+        class Stdin(io.StringIO):
+            reads = 0
+
+            def seekable(self):
+                return False
+
+            def read(self, *args):
+                self.reads += 1
+                return super().read(*args)
+
+        stream = Stdin("print(1)")
+        first = Source(stream)
+        second = Source(stream)
+        assert stream.reads == 1
+        assert first.text == second.text == "print(1)"
+
+    def test_seekable_stream_is_rewound(self):
+        stream = io.StringIO("print(1)")
+        assert Source(stream).text == Source(stream).text == "print(1)"
+
+    def test_fragment_line_and_column_offsets(self):
+        source = Source("x = 1\ny = z\n", name="doc.md", start_line=10, column_offset=4)
+        assert source.text == "x = 1\ny = z\n"
+        assert source.line_offset == 9
+
+        report = Report(source, 10, 11, config=Config(color=False))
+        report.label(0, 1, message="first")
+        report.label(10, 11, message="undefined")
+        assert render(report).splitlines()[1:9] == [
+            "    ╭─[ doc.md:11:5 ]",
+            "    │",
+            " 10 │     x = 1",
+            "    │     ┬  ",
+            "    │     ╰── first",
+            " 11 │ y = z",
+            "    │     ┬  ",
+            "    │     ╰── ^ undefined",
+        ]
+
+    def test_line_offset_and_start_line_are_exclusive(self):
+        assert Source("x", line_offset=4).line_offset == Source("x", start_line=5).line_offset
+        with pytest.raises(ValueError):
+            Source("x", line_offset=4, start_line=5)
+        with pytest.raises(ValueError):
+            Source("x", start_line=0)
+
+    def test_notebook_cells(self):
+        notebook = Source.from_cells(["x = 1", "y = x + z", "print(y)"], start=3)
+        assert [cell.path for cell in notebook.cells] == ["Cell [3]", "Cell [4]", "Cell [5]"]
+        assert notebook.text == "x = 1\ny = x + z\nprint(y)"
+
+        # Global spans and spans relative to a cell
+        report = Report(notebook, 14, 15, message="Undefined", config=Config(color=False))
+        report.label(14, 15, message="global")
+        report.label(6, 7, path="Cell [5]", message="relative")
+        output = render(report)
+        assert "╭─[ Cell [4]:1:9 ]" in output
+        assert "├─[ Cell [5]:1:7 ]" in output
+        assert "Cell [3]" not in output
+
+    def test_notebook_without_cells(self):
+        with pytest.raises(ValueError):
+            Source.from_cells([])
+
+    def test_source_map(self):
+        original = "let x = a ?? b;\n"
+        generated = "let x = (a != null ? a : b);\n"
+        source_map = SourceMap([(8, 27, "main.lox", 8, 14)])
+        source_map.add(9, 10, "main.lox", 8, 9)
+        assert len(source_map) == 2
+
+        source = Source(generated, name="<desugared>", source_map=source_map)
+        config = Config(color=False, show_generated=True)
+        report = Report(source, 8, 27, files={"main.lox": original}, config=config)
+        report.label(8, 27, message="expression")
+        report.label(9, 10, message="operand")
+        report.label(0, 3, message="unmapped")
+        output = render(report)
+        assert "╭─[ main.lox:1:9 ]" in output
+        assert "├─[ <desugared>:1:1 ]" in output
+        assert "Note 1: in generated code: (a != null ? a : b)" in output
+        assert "Note 2: in generated code: a" in output
+
+    def test_source_map_without_original_file(self):
+        source_map = SourceMap([(0, 5, "missing.lox", 0, 5)])
+        with pytest.raises(FileNotFoundError, match="missing.lox"):
+            Report(Source("print", source_map=source_map), 0, 5)
+
+    def test_source_map_loads_original_file(self, tmp_path):
+        original = tmp_path / "main.lox"
+        original.write_bytes("print «x»;\n".encode("latin-1"))
+        source_map = SourceMap([(0, 5, original, 0, 5)])
+        source = Source("print", source_map=source_map)
+        report = Report(source, 0, 5, encoding="latin-1", config=Config(color=False))
+        report.label(0, 5, message="here")
+        assert "print «x»;" in render(report)
+
+    def test_decoding_error_policy(self):
+        source = Source(b"ab\xffcd", errors="replace")
+        assert source.text == "ab\ufffdcd"
+        assert ":1:4" in self.render_label(source, 3, 4, byte_indexed=True)
+
+    def test_decoding_error_policy_changes_length(self):
+        # As many chars as bytes, but "\\xff" stands for the single byte 6
+        source = Source("ééé".encode() + b"\xffx", errors="backslashreplace")
+        assert source.text == "ééé\\xffx"
+        assert ":1:4" in self.render_label(source, 6, 7, byte_indexed=True)
+        assert ":1:8" in self.render_label(source, 7, 8, byte_indexed=True)
+
+
 class TestReport:
     def test_create_report_from_string(self):
         report = Report(
@@ -116,7 +351,7 @@ class TestReport:
             end=21,
             message="Print command does not require parenthesis",
             kind="warning",
-            config=Config(color=False),
+            config=Config(color=False, path_display="basename"),
         )
         report.label(
             start=5,
@@ -139,7 +374,7 @@ class TestReport:
         assert (
             output
             == """Warning: Print command does not require parenthesis
-   ╭─[ /home/chips/git/compiler/theseus/tests/example.lox:1:6 ]
+   ╭─[ example.lox:1:6 ]
    │
  1 │ print("Hello World!");
    │      │              │  
@@ -158,7 +393,7 @@ class TestReport:
             end=21,
             message="Print command does not require parenthesis",
             kind="warning",
-            config=Config(color=False, compact=True),
+            config=Config(color=False, compact=True, path_root=path.parent),
         )
         report.label(
             5,
@@ -181,9 +416,1113 @@ class TestReport:
         assert (
             output
             == """Warning: Print command does not require parenthesis
-   ╭─[ /home/chips/git/compiler/theseus/tests/example.lox:1:6 ]
+   ╭─[ example.lox:1:6 ]
  1 │print("Hello World!");
    │     ╰──────────────┼─ Parenthesis start here
    │                    ╰─ And end here
 """
         )
+
+
+class TestIncludes:
+    FILES = {
+        "main.lox": 'include "util.lox";\n',
+        "util.lox": 'include "deep.lox";\n',
+    }
+
+    def report(self, compact: bool) -> Report:
+        config = Config(color=False, compact=compact)
+        report = Report("x = ;\n", 4, 5, name="deep.lox", files=self.FILES, config=config)
+        report.label(4, 5, message="expected expression")
+        report.included_from("util.lox", 0, 19)
+        report.add_include(Label(0, 19, path="main.lox", message="included here"))
+        return report
+
+    def test_include_stack_as_snippets(self):
+        output = render(self.report(compact=False))
+        assert output.splitlines()[1] == "   ╭─[ deep.lox:1:5 ]"
+        assert output.index("util.lox:1:1") < output.index("main.lox:1:1")
+        assert "╰── included from here" in output
+
+    def test_include_files_are_loaded(self, tmp_path):
+        (tmp_path / "main.lox").write_bytes('include "ü.lox";\n'.encode("latin-1"))
+        report = Report("x = ;\n", 4, 5, name="ü.lox", config=Config(color=False, compact=True))
+        report.included_from(tmp_path / "main.lox", 0, 16, encoding="latin-1")
+        assert render(report).startswith(f"included from {tmp_path / 'main.lox'}:1:1\n")
+        with pytest.raises(FileNotFoundError):
+            report.add_include(Label(0, 1, path=str(tmp_path / "missing.lox")))
+
+    def test_compact_include_stack(self):
+        output = render(self.report(compact=True))
+        assert output.splitlines()[:3] == [
+            "included from util.lox:1:1",
+            "included from main.lox:1:1: included here",
+            "Error: ",
+        ]
+
+    def test_include_requires_path(self):
+        report = Report("x = ;", 4, 5)
+        with pytest.raises(ValueError):
+            report.add_include(Label(0, 1))
+
+
+class TestExpansions:
+    CODE = "macro_rules! double { ($x:expr) => { $x * 2 } }\nlet y = double!(z);\n"
+
+    def report(self, **kwargs) -> Report:
+        config = Config(color=False, **kwargs)
+        report = Report(self.CODE, 65, 66, config=config, message="unknown name")
+        report.label(65, 66, message="not found in this scope")
+        definition = Label(0, 47)
+        report.expanded_from("double", 56, 66, definition=definition)
+        return report
+
+    def test_expansion_as_labels(self):
+        output = render(self.report())
+        assert "in this expansion of `double`" in output
+        assert "`double` defined here" in output
+
+    def test_compact_expansion_notes(self):
+        output = render(self.report(compact=True))
+        assert "in expansion of `double` at <string>:2:9" in output
+        assert "defined here" not in output
+
+    def test_expansion_limit(self):
+        report = self.report(expansion_limit=1)
+        report.add_expansion(Expansion("outer", Label(48, 66)))
+        output = render(report)
+        assert "outer" not in output
+        assert "1 more macro expansion not shown" in output
+        report.add_expansion(Expansion("outermost", Label(48, 66)))
+        assert "2 more macro expansions not shown" in render(report)
+
+    def test_expansion_attributes(self):
+        report = Report(self.CODE, 0, 1)
+        expansion = report.expanded_from("double", 56, 66, path="lib.rs")
+        assert expansion.name == "double"
+        assert expansion.call_site == Label(56, 66, path="lib.rs")
+        assert expansion.definition is None
+
+
+class TestSubDiagnostics:
+    CODE = "let x = 1;\nlet x = 2;\n"
+
+    def report(self) -> Report:
+        config = Config(color=False)
+        report = Report(self.CODE, 15, 16, config=config, message="duplicate definition")
+        report.label(15, 16, message="redefined here")
+        return report
+
+    def test_note_with_span(self):
+        report = self.report()
+        report.add_note("previous definition here", 4, 5)
+        output = render(report)
+        assert output.index("Error: duplicate definition") < output.index(
+            "Note: previous definition here"
+        )
+        assert "\x1b" not in output
+        assert output.count("let x = 1;") == 1
+
+    def test_help_with_labels(self):
+        report = self.report()
+        report.add_help("rename it", labels=[Label(15, 16, message="use `y`")])
+        output = render(report)
+        assert "Help: rename it" in output
+        assert "use `y`" in output
+
+    def test_child_report(self):
+        report = self.report()
+        child = Report("x = 1", 0, 1, kind="note", message="from another file")
+        report.add_note(child)
+        report.add_note("plain note")
+        notes = report.notes
+        assert notes[0] is child
+        assert notes[1] == "plain note"
+        assert report.helps == []
+
+    def test_spanned_note_is_readable(self):
+        report = self.report()
+        report.add_note("previous definition here", 4, 5)
+        (note,) = report.notes
+        assert isinstance(note, Report)
+
+    def test_invalid_sub_diagnostics(self):
+        report = self.report()
+        with pytest.raises(ValueError):
+            report.add_note("missing end", 4)
+        with pytest.raises(ValueError):
+            report.add_note(report)
+        with pytest.raises(ValueError):
+            report.add_note(Report("x", 0, 1), 0, 1)
+        with pytest.raises(TypeError):
+            report.add_help(42)
+
+    def test_indirect_cycles_are_rejected(self):
+        a = Report("x = 1", 0, 1, message="a")
+        b = Report("x = 1", 0, 1, message="b")
+        c = Report("x = 1", 0, 1, message="c")
+        a.add_note(b)
+        b.add_help(c)
+        with pytest.raises(ValueError):
+            c.add_note(a)
+        with pytest.raises(ValueError):
+            c.helps = [a]
+        with pytest.raises(ValueError):
+            c.update(notes=[a])
+        assert c.notes == [] and c.helps == []
+        assert "c" in repr(a)
+
+
+class TestSuggestions:
+    CODE = "let x = 1;\nprintn!(x);\n"
+
+    def report(self, **kwargs) -> Report:
+        config = Config(**kwargs)
+        report = Report(self.CODE, 11, 17, config=config, message="unknown macro")
+        report.label(11, 17, message="not found")
+        return report
+
+    def test_replacement_as_diff(self):
+        report = self.report(color=False)
+        report.suggest(11, 17, "println", message="a similar macro exists")
+        lines = render(report).splitlines()
+        i = lines.index("Help: a similar macro exists")
+        assert lines[i + 3 :] == [" 2 │ - printn!(x);", " 2 │ + println!(x);", "───╯"]
+
+    def test_insertion_has_no_removed_lines(self):
+        report = self.report(color=False)
+        report.suggest(21, 21, ";\n")
+        output = render(report)
+        assert "Help: try this" in output
+        assert " 2 │ + printn!(x);\n 3 │ + ;" in output
+        assert " - " not in output
+
+    def test_colored_suggestion(self):
+        report = self.report(color=True)
+        report.suggest(11, 17, "println")
+        output = render(report)
+        assert "\x1b[9m\x1b[31mprintn\x1b[0m\x1b[32mprintln\x1b[0m!(x);" in output
+
+    def test_structured_suggestions(self):
+        report = self.report()
+        suggestion = report.suggest(
+            11, 17, "println", applicability="machine-applicable"
+        )
+        assert report.suggestions == [suggestion]
+        assert suggestion.replacement == "println"
+        assert suggestion.applicability == "machine-applicable"
+        assert (suggestion.start, suggestion.end, suggestion.path) == (11, 17, None)
+        report.add_suggestion(Suggestion(0, 3, "const"))
+        assert len(report.suggestions) == 2
+
+    def test_invalid_applicability(self):
+        with pytest.raises(ValueError):
+            Suggestion(0, 1, "x", applicability="sure")
+
+
+class TestApplyEdits:
+    def write(self, tmp_path, text: str, name="main.lox", **kwargs):
+        path = tmp_path / name
+        path.write_bytes(text.encode(**kwargs))
+        return str(path)
+
+    def test_return_texts(self, tmp_path):
+        path = self.write(tmp_path, "let x = 1;\r\nprint x;\r\n")
+        edits = [(path, 4, 5, "y"), (path, 18, 19, "y"), (path, 10, 10, " // ok")]
+        texts, conflicts = apply_edits(edits)
+        assert conflicts == []
+        assert texts == {path: "let y = 1; // ok\r\nprint y;\r\n"}
+
+    def test_byte_indexed_edits(self, tmp_path):
+        path = self.write(tmp_path, 'é = "ü"', encoding="latin-1")
+        config = Config(byte_indexed=True)
+        texts, _ = apply_edits([(path, 5, 6, "u")], config=config, encoding="latin-1")
+        assert texts[path] == 'é = "u"'
+
+    def test_write_files(self, tmp_path):
+        path = self.write(tmp_path, "é = 1\n", encoding="utf-16")
+        edit = Suggestion(4, 5, "2", path=path)
+        written, _ = apply_edits([edit], mode="write", encoding="utf-16")
+        assert written == [path]
+        assert (tmp_path / "main.lox").read_text(encoding="utf-16") == "é = 2\n"
+
+    def test_write_requires_strict_errors(self, tmp_path):
+        path = tmp_path / "main.lox"
+        path.write_bytes(b"a = 1 # \xff\n")
+        with pytest.raises(ValueError, match="errors='strict'"):
+            apply_edits([(str(path), 4, 5, "2")], mode="write", errors="replace")
+        assert path.read_bytes() == b"a = 1 # \xff\n"
+
+    def test_unified_diff(self, tmp_path):
+        path = self.write(tmp_path, "a = 1\nb = 2\n")
+        diff, _ = apply_edits([(path, 10, 11, "3")], mode="diff")
+        assert diff.splitlines() == [
+            f"--- a/{path}",
+            f"+++ b/{path}",
+            "@@ -1,2 +1,2 @@",
+            " a = 1",
+            "-b = 2",
+            "+b = 3",
+        ]
+
+    def test_conflicting_edits(self, tmp_path):
+        path = self.write(tmp_path, "let x = 1;\n")
+        other = self.write(tmp_path, "x\n", name="other.lox")
+        edits = [(path, 4, 9, "y = 2"), (path, 8, 9, "3"), (other, 0, 1, "y")]
+        texts, conflicts = apply_edits(edits, config=Config(color=False))
+        assert texts == {other: "y\n"}
+        (conflict,) = conflicts
+        output = render(conflict)
+        assert "Conflicting edits" in output
+        assert "first edit" in output
+        assert "conflicts with the first edit" in output
+        assert (tmp_path / "main.lox").read_text() == "let x = 1;\n"
+
+    def test_duplicate_edits_are_applied_once(self, tmp_path):
+        path = self.write(tmp_path, "x\n")
+        texts, conflicts = apply_edits([(path, 0, 0, "# "), (path, 0, 0, "# ")])
+        assert conflicts == []
+        assert texts[path] == "# x\n"
+
+    def test_invalid_edits(self, tmp_path):
+        path = self.write(tmp_path, "é\n")
+        with pytest.raises(ValueError):
+            apply_edits([(path, 1, 1, "x")], config=Config(byte_indexed=True))
+        with pytest.raises(ValueError):
+            apply_edits([(path, 0, 10, "x")])
+        with pytest.raises(ValueError):
+            apply_edits([Suggestion(0, 1, "x")])
+        with pytest.raises(ValueError):
+            apply_edits([(path, 0, 1, "x")], mode="patch")
+
+
+class TestInsertionPoints:
+    CODE = "let x = 1\nprint x\n"
+
+    def render(self, offset: int, compact=False) -> list[str]:
+        config = Config(color=False, compact=compact)
+        report = Report(self.CODE, offset, offset, config=config, message="expected `;`")
+        report.add_label(Label.insertion(offset, message="here"))
+        return render(report).splitlines()
+
+    def test_end_of_line(self):
+        lines = self.render(9)
+        assert lines[3:6] == [" 1 │ let x = 1", "   │          │ ", "   │          ╰─ ‸ here"]
+
+    def test_end_of_file_after_newline(self):
+        lines = self.render(18)
+        assert lines[1] == "   ╭─[ <string>:2:8 ]"
+        assert lines[3:6] == [" 2 │ print x", "   │        │ ", "   │        ╰─ ‸ here"]
+
+    def test_past_end_of_file(self):
+        assert self.render(30) == self.render(18)
+
+    def test_end_of_file_without_newline(self):
+        report = Report("let x = 1", 20, 20, config=Config(color=False))
+        report.add_label(Label.insertion(20, message="here"))
+        lines = render(report).splitlines()
+        assert lines[1] == "   ╭─[ <string>:1:10 ]"
+        assert lines[3:6] == [" 1 │ let x = 1", "   │          │ ", "   │          ╰─ ‸ here"]
+
+    def test_differs_from_one_char_label(self):
+        def render_label(label: Label, ascii=False) -> list[str]:
+            report = Report(self.CODE, 4, 4, config=Config(color=False, ascii=ascii))
+            report.add_label(label)
+            return render(report).splitlines()[3:6]
+
+        assert render_label(Label.insertion(4, message="here")) == [
+            " 1 │ let x = 1",
+            "   │     │ ",
+            "   │     ╰─ ‸ here",
+        ]
+        assert render_label(Label(4, 5, message="here")) == [
+            " 1 │ let x = 1",
+            "   │     ┬  ",
+            "   │     ╰── here",
+        ]
+        assert render_label(Label.insertion(4, message="here"), ascii=True)[2] == "   |     `- ^ here"
+
+    def test_compact(self):
+        assert self.render(18, compact=True)[1:] == [
+            "   ╭─[ <string>:2:8 ]",
+            " 2 │print x",
+            "   │       ╰ ‸ here",
+        ]
+
+    def test_insertion_without_message(self):
+        report = Report(self.CODE, 9, 9, config=Config(color=False))
+        report.add_label(Label.insertion(9))
+        assert "╰─ ‸" in render(report)
+
+    def test_insertion_label(self):
+        label = Label.insertion(3, path="main.lox")
+        assert label == Label(3, 3, path="main.lox")
+        assert label.is_insertion
+        assert not Label(3, 4).is_insertion
+
+
+class TestPrimaryLabels:
+    def test_primary_field(self):
+        label = Label(1, 2, message="here", primary=True)
+        assert label.primary
+        assert repr(label) == 'Label(1, 2, message="here", primary=True)'
+        assert label != Label(1, 2, message="here")
+        assert not label.copy(primary=False).primary
+        assert label.copy(message="there").primary
+
+    def test_implicit_primary_label(self):
+        report = Report("let x = 1;\n", 4, 5, config=Config(color=False))
+        lines = render(report).splitlines()
+        assert lines[3:7] == [" 1 │ let x = 1;", "   │     ┬  ", "   │     ╰── ^", "───╯"]
+
+    def test_primary_marker_without_colors(self):
+        report = Report("let x = 1;\n", 4, 5, config=Config(color=False))
+        report.add_label(Label(4, 5, message="primary"))
+        report.add_label(Label(8, 9, message="secondary"))
+        assert render(report).splitlines()[3:8] == [
+            " 1 │ let x = 1;",
+            "   │     ┬   ┬  ",
+            "   │     ╰───┼── ^ primary",
+            "   │         │  ",
+            "   │         ╰── secondary",
+        ]
+
+    def test_primary_uses_kind_color(self):
+        report = Report("let x = 1;\n", 4, 5, kind="warning")
+        report.add_label(Label(4, 5, message="on the report span"))
+        report.add_label(Label(8, 9, message="secondary"))
+        output = render(report)
+        assert "\x1b[33m┬" in output
+        # Secondary labels keep ariadne's default style
+        assert output.count("\x1b[33m┬") == 1
+        assert "\x1b[0m   ┬  " in output
+
+
+class TestMultiRangeLabels:
+    CODE = "f(a, b,\n  c)\n"
+
+    def test_single_message(self):
+        report = Report(self.CODE, 0, 1, config=Config(color=False), message="mismatched")
+        message = "these arguments have mismatched types"
+        report.add_label(Label.multi([(2, 3), (5, 6), (10, 11)], message=message))
+        assert render(report).splitlines()[3:] == [
+            " 1 │ f(a, b,",
+            "   │   ┬  ┬  ",
+            "   │   ╰──┼── …",
+            "   │      │  ",
+            "   │      ╰── …",
+            " 2 │   c)",
+            "   │   ┬  ",
+            "   │   ╰── " + message,
+            "───╯",
+        ]
+
+    def test_ranges_across_lines(self):
+        code = "foo(a,\n    b,\n    c)\n"
+        report = Report(code, 18, 19, config=Config(color=False, ascii=True))
+        report.add_label(Label.multi([(4, 5), (11, 12), (18, 19)], message="here"))
+        assert render(report).splitlines()[3:] == [
+            " 1 | foo(a,",
+            "   |     |  ",
+            "   |     `-- ...",
+            " 2 |     b,",
+            "   |     |  ",
+            "   |     `-- ...",
+            " 3 |     c)",
+            "   |     |  ",
+            "   |     `-- here",
+            "---'",
+        ]
+
+    def test_ranges_on_the_message_line(self):
+        report = Report("let x = f(a, b);\n", 4, 5, config=Config(color=False))
+        report.add_label(Label.multi([(10, 11), (13, 14)], message="arguments"))
+        assert render(report) == (
+            "Error: \n"
+            "   ╭─[ <string>:1:5 ]\n"
+            "   │\n"
+            " 1 │ let x = f(a, b);\n"
+            "   │           ┬  ┬  \n"
+            "   │           ╰──┼── …\n"
+            "   │              │  \n"
+            "   │              ╰── arguments\n"
+            "───╯\n"
+        )
+
+    def test_ranges(self):
+        label = Label.multi([(2, 3), (5, 6)], path="main.lox", primary=True)
+        assert label.ranges == [(2, 3), (5, 6)]
+        assert Label(2, 3).ranges == [(2, 3)]
+        assert repr(label) == 'Label.multi([(2, 3), (5, 6)], path="main.lox", primary=True)'
+        assert label != Label(2, 3, path="main.lox", primary=True)
+        assert label.copy(message="x").ranges == label.ranges
+
+    def test_invalid_ranges(self):
+        with pytest.raises(ValueError):
+            Label.multi([])
+        with pytest.raises(ValueError):
+            Label.multi([(3, 2)])
+
+
+class TestLabelSearch:
+    CODE = "\ufeffprint (1)\r\nfoo = foo\r\n"
+
+    def report(self, byte_indexed=False) -> Report:
+        config = Config(byte_indexed=byte_indexed)
+        return Report(self.CODE.encode(), 0, 1, config=config)
+
+    def test_label_text(self):
+        label = self.report().label_text("foo", occurrence=2)
+        assert label.ranges == [(18, 21)]
+        assert self.CODE[18:21] == "foo"
+
+    def test_label_text_byte_indexed(self):
+        label = self.report(byte_indexed=True).label_text("foo", occurrence=2)
+        assert label.ranges == [(20, 23)]
+
+    def test_label_regex(self):
+        report = self.report()
+        assert report.label_regex(r"print\s*(\()", group=1).ranges == [(7, 8)]
+        assert report.label_regex(r"(?P<name>\w+) =", group="name").ranges == [(12, 15)]
+
+    def test_line_range(self):
+        report = Report("foo\nbar foo\n", 0, 1)
+        assert report.label_text("foo", lines=(2, 2)).ranges == [(8, 11)]
+        with pytest.raises(ValueError):
+            report.label_text("foo", lines=(3, 4))
+
+    def test_missing_match(self):
+        report = self.report()
+        with pytest.raises(ValueError, match="Occurrence 3 of \"foo\" not found"):
+            report.label_text("foo", occurrence=3)
+        with pytest.raises(ValueError):
+            report.label_regex("bar")
+        with pytest.raises(ValueError):
+            report.label_regex("(x)?print", group=1)
+
+
+class TestAstLabels:
+    CODE = 'x = "héllo" + y\nprint(ñ)\r\nz\n'
+
+    def node(self, name):
+        tree = ast.parse(self.CODE.replace("\r\n", "\n"))
+        return next(n for n in ast.walk(tree) if isinstance(n, ast.Name) and n.id == name)
+
+    def test_label_from_ast_non_ascii(self):
+        label = Label.from_ast(self.node("y"), self.CODE)
+        assert label.ranges == [(14, 15)]
+        assert self.CODE[14:15] == "y"
+
+    def test_label_from_ast_byte_indexed(self):
+        label = Label.from_ast(self.node("ñ"), self.CODE, byte_indexed=True)
+        start, end = label.ranges[0]
+        assert self.CODE.encode()[start:end].decode() == "ñ"
+
+    def test_crlf_lines(self):
+        label = Label.from_ast(self.node("z"), self.CODE)
+        assert label.ranges == [(26, 27)]
+        assert self.CODE[26:27] == "z"
+
+    def test_label_node(self):
+        report = Report(self.CODE, 0, 1)
+        label = report.label_node(self.node("ñ"), message="here")
+        assert label.ranges == [(22, 23)]
+
+    def test_report_from_ast(self):
+        config = Config(byte_indexed=True)
+        call = next(n for n in ast.walk(ast.parse("é = f(1)\n")) if isinstance(n, ast.Call))
+        report = Report.from_ast(call, "é = f(1)\n", message="bad call", config=config)
+        report.label(5, 9, message="call")
+        output = render(report)
+        assert "bad call" in output
+        assert "<string>:1:5" in output
+
+    def test_tokens(self):
+        code = "é = 'ñ' + x\n"
+        tokens = list(tokenize.generate_tokens(io.StringIO(code).readline))
+        name = next(t for t in tokens if t.string == "x")
+        assert Label.from_ast(name, code).ranges == [(10, 11)]
+        end = tokens[-1]
+        assert Label.from_ast(end, code).ranges == [(12, 12)]
+
+    def test_invalid_node(self):
+        with pytest.raises(TypeError):
+            Label.from_ast(ast.parse("x"), "x")
+
+
+def fake(module, **attrs):
+    cls = type("Fake", (), {"__module__": module})
+    obj = cls()
+    obj.__dict__.update(attrs)
+    return obj
+
+
+class TestSpanProtocol:
+    CODE = "é = foo(bar)\n"
+
+    def test_dunder_span(self):
+        class Node:
+            def __theseus_span__(self):
+                return (4, 7)
+
+        assert Label(Node()).ranges == [(4, 7)]
+        report = Report(self.CODE, Node())
+        assert report.label(Node(), message="call").ranges == [(4, 7)]
+
+    def test_span_units(self):
+        class Node:
+            def __theseus_span__(self):
+                return (5, 8, "bytes")
+
+        assert Report(self.CODE, 0, 1).label(Node()).ranges == [(4, 7)]
+        config = Config(byte_indexed=True)
+        assert Report(self.CODE, 0, 1, config=config).label(Node()).ranges == [(5, 8)]
+
+    def test_tree_sitter(self):
+        node = fake("tree_sitter", start_byte=9, end_byte=12)
+        assert Report(self.CODE, 0, 1).label(node).ranges == [(8, 11)]
+
+    def test_label_keeps_span_units(self):
+        node = fake("tree_sitter", start_byte=9, end_byte=12)
+        label = Label(node, message="arg")
+        assert label.ranges == [(9, 12)]
+        assert repr(label) == 'Label((9, 12, "bytes"), message="arg")'
+        assert pickle.loads(pickle.dumps(label)) == label
+
+        config = Config(color=False)
+        expected = Report(self.CODE, 0, 1, labels=[Label(8, 11, message="arg")], config=config)
+        report = Report(self.CODE, 0, 1, labels=[label], config=config)
+        assert render(report) == render(expected)
+        config = Config(color=False, byte_indexed=True)
+        expected = Report(self.CODE, 0, 1, labels=[Label(9, 12, message="arg")], config=config)
+        report = Report(self.CODE, 0, 1, labels=[label], config=config)
+        assert render(report) == render(expected)
+
+    def test_lark(self):
+        token = fake("lark.lexer", start_pos=4, end_pos=7)
+        tree = fake("lark.tree", meta=fake("lark.tree", start_pos=4, end_pos=12))
+        report = Report(self.CODE, token)
+        assert report.label(tree).ranges == [(4, 12)]
+        with pytest.raises(ValueError, match="propagate_positions"):
+            report.label(fake("lark.tree", meta=fake("lark.tree")))
+
+    def test_sly(self):
+        assert Label(fake("sly.lex", index=8, value="bar")).ranges == [(8, 11)]
+        assert Label(fake("sly.lex", index=8, end=12, value="x")).ranges == [(8, 12)]
+
+    def test_pyparsing(self):
+        label = Label(fake("pyparsing.exceptions", loc=8))
+        assert label.ranges == [(8, 8)]
+        assert label.is_insertion
+
+    def test_registered_adapter(self):
+        class Base:
+            pass
+
+        class Child(Base):
+            pass
+
+        register_span_adapter(Base, lambda obj: (1, 3))
+        assert Label(Child()).ranges == [(1, 3)]
+
+    def test_invalid_spans(self):
+        with pytest.raises(TypeError, match="end is required"):
+            Label(1)
+        with pytest.raises(TypeError):
+            Label(object())
+        with pytest.raises(TypeError, match="together with a span"):
+            Label(fake("pyparsing", loc=1), 2)
+
+
+def fake_exception(module, name, **attrs):
+    cls = type(name, (Exception,), {"__module__": module})
+    exc = cls()
+    exc.__dict__.update(attrs)
+    return exc
+
+
+class TestFromException:
+    CODE = "é = 1\nx = é é\n"
+
+    def syntax_error(self, code):
+        try:
+            compile(code, "<string>", "exec")
+        except SyntaxError as exc:
+            return exc
+        raise AssertionError("expected a SyntaxError")
+
+    def test_syntax_error(self):
+        report = Report.from_exception(self.syntax_error(self.CODE), self.CODE)
+        output = render(report)
+        assert "invalid syntax" in output
+        assert "<string>:2:7" in output
+
+    def test_syntax_error_byte_indexed(self):
+        exc = self.syntax_error(self.CODE)
+        config = Config(byte_indexed=True, color=False)
+        output = render(Report.from_exception(exc, self.CODE, config=config))
+        assert "<string>:2:7" in output
+
+    def test_syntax_error_from_file(self, tmp_path):
+        path = tmp_path / "mod.py"
+        path.write_text(self.CODE, encoding="utf-8")
+        try:
+            compile(self.CODE, str(path), "exec")
+        except SyntaxError as exc:
+            output = render(Report.from_exception(exc))
+        assert "mod.py:2:7" in output
+
+    def test_syntax_error_from_text(self):
+        output = render(Report.from_exception(self.syntax_error(self.CODE)))
+        assert "<string>:2:7" in output
+        assert "é = 1" not in output
+
+    def test_lark_unexpected_token(self):
+        code = "foo(1 2)"
+        token = fake("lark.lexer", type="NUMBER", start_pos=6, end_pos=7)
+        token.__class__.__str__ = lambda self: "2"
+        exc = fake_exception("lark.exceptions", "UnexpectedToken", token=token, expected={"RPAR", "COMMA"})
+        output = render(Report.from_exception(exc, code, config=Config(color=False)))
+        assert 'Unexpected token "2"' in output
+        assert "<string>:1:7" in output
+        assert "expected one of: COMMA, RPAR" in output
+
+    def test_lark_unexpected_eof(self):
+        exc = fake_exception("lark.exceptions", "UnexpectedEOF", expected=["RPAR"], pos_in_stream=-1)
+        output = render(Report.from_exception(exc, "foo(1", config=Config(color=False)))
+        assert "Unexpected end of input" in output
+        assert "expected RPAR" in output
+        with pytest.raises(ValueError, match="source is required"):
+            Report.from_exception(exc)
+
+    def test_pyparsing(self):
+        exc = fake_exception("pyparsing.exceptions", "ParseException", loc=5, msg="Expected ';'", pstr="x = 1")
+        output = render(Report.from_exception(exc, config=Config(color=False)))
+        assert "Expected ';'" in output
+        assert "<string>:1:6" in output
+
+    def test_registered_converter(self):
+        class CompileError(Exception):
+            pass
+
+        class NameError_(CompileError):
+            pass
+
+        register_exception_converter(CompileError, lambda exc: ((4, 5), str(exc), ["name"]))
+        output = render(Report.from_exception(NameError_("unknown name"), self.CODE))
+        assert "unknown name" in output
+        assert "expected name" in output
+
+    def test_unknown_exception(self):
+        with pytest.raises(TypeError, match="No converter for KeyError"):
+            Report.from_exception(KeyError("x"), "x")
+
+
+class TestReportError:
+    def reports(self):
+        config = Config(color=False)
+        first = Report("x = 1\n", 0, 1, message="bad name", config=config)
+        second = Report("y = 2\n", 4, 5, message="bad value", kind="warning")
+        return first, second
+
+    def test_raise(self):
+        first, _ = self.reports()
+        with pytest.raises(ReportError) as info:
+            raise ReportError(first)
+        assert info.value.report is first
+        assert isinstance(info.value, Exception)
+
+    def test_str_has_no_colors(self):
+        first, second = self.reports()
+        text = str(ReportError([first, second]))
+        assert text.startswith("Error: bad name")
+        assert "Warning: bad value" in text
+        assert "\x1b[" not in text
+
+    def test_group(self):
+        if not hasattr(builtins, "ExceptionGroup"):
+            pytest.importorskip("exceptiongroup")
+        first, second = self.reports()
+        group = ReportError([first, second]).group()
+        assert str(group).startswith("2 errors")
+        assert [exc.report for exc in group.exceptions] == [first, second]
+        assert ReportError(group).reports == [first, second]
+
+    def test_invalid(self):
+        with pytest.raises(ValueError):
+            ReportError([])
+        with pytest.raises(TypeError):
+            ReportError(["not a report"])
+
+
+class TestExceptHook:
+    def run_hook(self, exc):
+        previous = sys.excepthook
+        try:
+            install_excepthook(config=Config(color=False))
+            with redirect_stderr(io.StringIO()) as data:
+                sys.excepthook(type(exc), exc, exc.__traceback__)
+        finally:
+            sys.excepthook = previous
+        return data.getvalue()
+
+    def fail(self):
+        return {"a": 1}["ñ"]
+
+    def test_renders_frames(self):
+        try:
+            self.fail()
+        except KeyError as exc:
+            output = self.run_hook(exc)
+        assert "Error: KeyError: 'ñ'" in output
+        assert "test_examples.py" in output
+        assert "in fail" in output
+        assert "in test_renders_frames" in output
+
+    def test_chained_exceptions(self):
+        try:
+            try:
+                self.fail()
+            except KeyError as exc:
+                raise ValueError("bad input") from exc
+        except ValueError as exc:
+            output = self.run_hook(exc)
+        assert output.index("KeyError") < output.index("direct cause") < output.index("ValueError: bad input")
+
+    def test_report_error(self):
+        report = Report("x = 1\n", 0, 1, message="bad name", config=Config(color=False))
+        output = self.run_hook(ReportError(report))
+        assert output.startswith("Error: bad name")
+
+    def test_report_error_uses_hook_config(self):
+        report = Report("x = 1\n", 0, 1, message="bad name", config=Config(color=True, compact=True))
+        output = self.run_hook(ReportError(report))
+        assert "\x1b[" not in output
+        assert "╭─" in output
+
+    def test_fallback(self):
+        output = self.run_hook(ValueError("not raised"))
+        assert "ValueError: not raised" in output
+        assert "╭─" not in output
+
+
+class TestReportHandler:
+    def logger(self, name):
+        stream = io.StringIO()
+        handler = ReportHandler(stream=stream, config=Config(color=False))
+        handler.setFormatter(logging.Formatter("%(levelname)s: %(message)s"))
+        logger = logging.getLogger(f"theseus.tests.{name}")
+        logger.propagate = False
+        logger.setLevel(logging.DEBUG)
+        logger.handlers = [handler]
+        return logger, stream
+
+    def test_is_a_handler(self):
+        assert isinstance(ReportHandler(), logging.Handler)
+        assert ReportHandler(logging.WARNING).level == logging.WARNING
+
+    def test_plain_records(self):
+        logger, stream = self.logger("plain")
+        logger.info("hello %s", "world")
+        assert stream.getvalue() == "INFO: hello world\n"
+
+    def test_source_and_span(self):
+        logger, stream = self.logger("span")
+        logger.warning("unused %s", "y", extra={"source": "y = 1\n", "span": (0, 1)})
+        output = stream.getvalue()
+        assert output.startswith("Warning: unused y")
+        assert "<string>:1:1" in output
+        assert "╰── ^" in output
+
+    def test_attached_report(self):
+        logger, stream = self.logger("report")
+        report = Report("z = 2\n", 0, 1, message="boom", kind="warning", config=Config(color=False))
+        logger.debug(report)
+        logger.error("ignored", extra={"report": report})
+        output = stream.getvalue()
+        assert output.startswith("Advice: boom")
+        assert "Error: boom" in output
+        assert "Warning" not in output
+
+    def test_level_filtering(self):
+        logger, stream = self.logger("level")
+        logger.handlers[0].setLevel(logging.ERROR)
+        logger.warning("skipped", extra={"source": "x", "span": (0, 1)})
+        assert stream.getvalue() == ""
+
+
+class TestWarnings:
+    CODE = "a = 1\nold_feature()\n"
+
+    def report(self):
+        config = Config(color=False)
+        return Report(self.CODE, 6, 17, message="old_feature is deprecated", config=config)
+
+    def test_warn(self):
+        report = self.report()
+        with warnings.catch_warnings(record=True) as caught:
+            warnings.simplefilter("always")
+            warn(report, category=DeprecationWarning)
+        [warning] = caught
+        assert warning.category is DeprecationWarning
+        assert (warning.filename, warning.lineno) == ("<string>", 2)
+        assert str(warning.message) == "old_feature is deprecated"
+        assert warning.message.report is report
+
+    def test_warn_inside_a_character(self):
+        # Byte offset 12 falls inside "é", which is still on the second line
+        config = Config(color=False, byte_indexed=True)
+        report = Report("a = 1\nb = \"é\"\nc = 3\n", 12, 13, message="odd", config=config)
+        with warnings.catch_warnings(record=True) as caught:
+            warnings.simplefilter("always")
+            warn(report)
+        assert caught[0].lineno == 2
+
+    def test_same_line_in_other_files(self):
+        names = ["a.lox", "b.lox"]
+        reports = [Report(self.CODE, 6, 17, name=name, message="deprecated") for name in names]
+        with warnings.catch_warnings(record=True) as caught:
+            warnings.simplefilter("default")
+            for report in reports * 2:
+                warn(report)
+        assert [warning.filename for warning in caught] == ["a.lox", "b.lox"]
+
+    def test_filters(self):
+        with warnings.catch_warnings(record=True) as caught:
+            warnings.simplefilter("always")
+            warnings.filterwarnings("ignore", category=DeprecationWarning)
+            warn(self.report(), category=DeprecationWarning)
+        assert caught == []
+        with warnings.catch_warnings():
+            warnings.simplefilter("error")
+            with pytest.raises(UserWarning) as info:
+                warn(self.report())
+        assert isinstance(info.value.report, Report)
+
+    def test_invalid_category(self):
+        with pytest.raises(TypeError):
+            warn(self.report(), category=ValueError)
+
+    def test_showwarning(self):
+        file = io.StringIO()
+        with warnings.catch_warnings(record=True) as caught:
+            warnings.simplefilter("always")
+            warn(self.report(), category=FutureWarning)
+        showwarning(*self.args(caught[0]), file=file)
+        output = file.getvalue()
+        assert output.startswith("Warning: old_feature is deprecated")
+        assert "<string>:2:1" in output
+
+    def test_showwarning_plain(self):
+        file = io.StringIO()
+        with warnings.catch_warnings(record=True) as caught:
+            warnings.simplefilter("always")
+            warnings.warn("plain", UserWarning)
+        showwarning(*self.args(caught[0]), file=file)
+        assert "UserWarning: plain" in file.getvalue()
+
+    def args(self, warning):
+        return warning.message, warning.category, warning.filename, warning.lineno
+
+
+class TestReportProperties:
+    def report(self):
+        report = Report("x = 1\n", 0, 1, code="E01", message="bad name")
+        report.label(4, 5, message="value")
+        report.add_note("a note")
+        return report
+
+    def test_getters(self):
+        report = self.report()
+        assert report.code == "E01"
+        assert report.message == "bad name"
+        assert report.kind == "error"
+        assert report.span == (0, 1)
+        assert [label.ranges for label in report.labels] == [[(4, 5)]]
+        assert report.notes == ["a note"]
+        assert report.helps == []
+
+    def test_setters(self):
+        report = self.report()
+        report.code = None
+        report.message = "renamed"
+        report.kind = "warning"
+        report.span = (4, 5)
+        report.helps = ["try this"]
+        assert (report.code, report.message, report.kind, report.span) == (None, "renamed", "warning", (4, 5))
+        assert report.helps == ["try this"]
+        with pytest.raises(ValueError):
+            report.kind = "custom"
+        with pytest.raises(ValueError):
+            report.span = (5, 4)
+        with pytest.raises(ValueError):
+            report.notes = [report]
+
+    def test_labels_are_immutable(self):
+        report = self.report()
+        assert isinstance(report.labels, tuple)
+        with pytest.raises(AttributeError):
+            report.labels = ()
+
+    def test_eq(self):
+        assert self.report() == self.report()
+        other = self.report()
+        other.message = "other"
+        assert self.report() != other
+        assert self.report() != "report"
+        with pytest.raises(TypeError):
+            hash(self.report())
+
+    def test_copy(self):
+        report = self.report()
+        copy = report.copy()
+        assert copy == report and copy is not report
+        copy.add_note("only in the copy")
+        assert report.notes == ["a note"]
+
+    def test_copy_with_changes(self):
+        report = self.report()
+        copy = report.copy(message="changed", kind="lint", color=Color("red"), labels=[])
+        assert copy.message == "changed"
+        assert copy.kind == "lint"
+        assert copy.labels == ()
+        assert report.message == "bad name"
+        with pytest.raises(TypeError, match="unexpected keyword argument 'foo'"):
+            report.copy(foo=1)
+
+    def test_copy_keeps_color_sequence(self):
+        report = self.report()
+        copy = report.copy()
+        assert copy.color() == report.color()
+        generator = ColorGenerator()
+        colors = [generator.next() for _ in range(3)]
+        assert Report("x", 0, 1).color() == colors[0]
+        assert report.color() == colors[2]
+
+    def test_repr(self):
+        report = Report("x = 1\n", 0, 1, message="bad", kind="warning", helps=["h"])
+        assert repr(report) == 'Report("<string>", 0, 1, message="bad", kind="warning", helps=["h"])'
+
+
+class TestPickle:
+    def roundtrip(self, obj):
+        return pickle.loads(pickle.dumps(obj))
+
+    def report(self) -> Report:
+        source = Source(b"a = 1\r\nb = caf\xe9\r\n", encoding="latin-1")
+        report = Report(source, 0, 1, code="E1", message="bad", kind="lint", color=Color("cyan"))
+        report.add_label(Label.multi([(0, 1), (4, 5)], message="here", color=Color.rgb(1, 2, 3)))
+        report.add_help("a help")
+        report.add_note("a child note", 6, 7)
+        report.add_suggestion(Suggestion(0, 1, "x", message="rename"))
+        report.add_expansion(Expansion("m", Label(4, 5)))
+        return report
+
+    def test_colors(self):
+        for color in [Color("red"), Color(42), Color.rgb(1, 2, 3)]:
+            assert self.roundtrip(color) == color
+
+    def test_color_generator(self):
+        generator = ColorGenerator()
+        generator.next()
+        restored = self.roundtrip(generator)
+        assert restored.next() == generator.next()
+
+    def test_config(self):
+        config = Config(compact=True, tab_width=2, label_attach="start", path_display="relative", path_root="/src")
+        assert self.roundtrip(config) == config
+        assert copy.deepcopy(Config()) == Config()
+
+    def test_label(self):
+        label = Label.multi([(0, 1), (3, 4)], path="a.py", message="m", order=1, primary=True)
+        assert self.roundtrip(label) == label
+        assert self.roundtrip(Label(1, 2)) == Label(1, 2)
+
+    def test_report(self):
+        report = self.report()
+        restored = self.roundtrip(report)
+        assert restored == report
+        assert render(restored) == render(report)
+        assert restored.color() == report.color()
+
+    def test_deepcopy(self):
+        report = self.report()
+        copied = copy.deepcopy(report)
+        assert copied == report
+        assert copied.notes[-1] is not report.notes[-1]
+        copied.add_note("only in the copy")
+        assert len(report.notes) == 1
+
+    def test_source_text_is_stored_once(self):
+        text = "x = 1\n" * 10_000
+        source = Source(text)
+        reports = [Report(source, i, i + 1) for i in range(20)]
+        data = pickle.dumps(reports)
+        assert len(data) < 2 * len(text)
+        loaded = pickle.loads(data)
+        assert loaded == reports
+        # The loaded reports share their source too
+        assert len(pickle.dumps(loaded)) == len(data)
+
+    def test_notebook_source(self):
+        source = Source.from_cells(["a = 1", "b = 2\r\n"])
+        report = Report(source, 8, 9, message="in a cell")
+        assert render(self.roundtrip(report)) == render(report)
+
+
+class TestFluentReport:
+    source = "def f(x):\n    return y\n"
+
+    def test_chaining(self):
+        report = (
+            Report(self.source, 21, 22, message="undefined name")
+            .with_label(21, 22, message="not defined")
+            .with_label(Label(6, 7, message="did you mean x?"))
+            .with_note("names must be defined before use")
+            .with_help("define y", 0, 3)
+            .with_code("E001")
+        )
+        assert report.code == "E001"
+        assert [label.ranges for label in report.labels] == [[(21, 22)], [(6, 7)]]
+        assert report.notes == ["names must be defined before use"]
+        assert isinstance(report.helps[0], Report)
+        assert "did you mean x?" in render(report)
+
+    def test_chaining_returns_the_same_report(self):
+        report = Report(self.source, 21, 22)
+        assert report.with_code("E001") is report
+        assert report.with_code(None).code is None
+
+    def test_label_options_with_label_object(self):
+        report = Report(self.source, 21, 22)
+        with pytest.raises(TypeError):
+            report.with_label(Label(6, 7), message="extra")
+
+    def test_extend_labels(self):
+        report = Report(self.source, 21, 22)
+        report.add_label(Label(0, 3))
+        report.extend_labels([Label(4, 5), Label(6, 7)])
+        assert [label.ranges for label in report.labels] == [[(0, 3)], [(4, 5)], [(6, 7)]]
+
+    def test_extend_labels_from_iterable(self):
+        report = Report(self.source, 21, 22)
+        report.extend_labels(Label(i, i + 1) for i in range(3))
+        assert [label.ranges for label in report.labels] == [[(0, 1)], [(1, 2)], [(2, 3)]]
+        with pytest.raises(TypeError):
+            report.extend_labels(iter([Label(4, 5), "not a label"]))
+        assert len(report.labels) == 3
+
+    def test_update(self):
+        report = Report(self.source, 21, 22, notes=["old"])
+        report.update(notes=["a", "b"], message="updated", kind="warning")
+        assert report.notes == ["a", "b"]
+        assert (report.message, report.kind) == ("updated", "warning")
+
+    def test_update_is_atomic(self):
+        report = Report(self.source, 21, 22, message="original")
+        with pytest.raises(TypeError, match="update\\(\\) got an unexpected keyword argument 'foo'"):
+            report.update(message="changed", foo=1)
+        with pytest.raises(ValueError):
+            report.update(message="changed", notes=[report])
+        assert report.message == "original"
+        assert report.notes == []
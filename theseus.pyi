@@ -1,10 +1,54 @@
+import ast
+import logging
+import re
+import tokenize
 from pathlib import Path
-from typing import Literal, TextIO
+from typing import Any, BinaryIO, Callable, Iterable, Literal, Protocol, TextIO
 
-__all__ = ["Config", "Color", "ColorGenerator", "Label", "Report"]
+__all__ = [
+    "Config",
+    "Color",
+    "ColorGenerator",
+    "Expansion",
+    "Label",
+    "Report",
+    "ReportError",
+    "ReportHandler",
+    "Source",
+    "SourceMap",
+    "Suggestion",
+    "apply_edits",
+    "install_excepthook",
+    "register_exception_converter",
+    "register_span_adapter",
+    "showwarning",
+    "warn",
+]
 
 NOT_GIVEN = NotImplemented
 
+SpanTuple = tuple[int, int] | tuple[int, int, Literal["chars", "bytes"]]
+
+class SpanLike(Protocol):
+    """
+    Objects that can be passed instead of a pair of offsets.
+
+    Besides objects implementing this protocol, theseus understands
+    tree-sitter nodes, lark tokens and trees (parsed with
+    propagate_positions=True), sly tokens, pyparsing exceptions and any type
+    registered with `register_span_adapter`. These libraries are never
+    imported by theseus.
+    """
+
+    def __theseus_span__(self) -> SpanTuple:
+        """
+        Return the (start, end) offsets of the object.
+
+        A third "chars" or "bytes" element gives the units of the offsets,
+        which are then converted to the units of the report. Otherwise they
+        are used as they are.
+        """
+
 class Config:
     """
     Configuration options for a report.
@@ -22,6 +66,12 @@ class Config:
         ascii: bool = False,
         byte_indexed: bool = False,
         label_attach: Literal["start", "middle", "end"] = "middle",
+        path_display: (
+            Literal["full", "relative", "basename", "uri"] | Callable[[str], str]
+        ) = "full",
+        path_root: str | Path = NOT_GIVEN,
+        show_generated: bool = False,
+        expansion_limit: int = NOT_GIVEN,
     ):
         """
         Args:
@@ -52,6 +102,23 @@ class Config:
                 it is a string.
             label_attach:
                 Where inline labels should attach to their spans.
+            path_display:
+                How file paths are shown in the report. "full" shows the path
+                as given, "relative" shows it relative to the current
+                directory (or to path_root), "basename" shows only the file
+                name and "uri" shows a file:// URI. A callable receives the
+                path as a string and returns the name to display. This only
+                affects rendering, files are still loaded from their real
+                paths.
+            path_root:
+                Root directory for path_display="relative". Giving a root
+                implies relative paths.
+            show_generated:
+                If true, labels moved to their original files by a SourceMap
+                add a note showing the generated code they point to.
+            expansion_limit:
+                Maximum number of macro expansion frames shown in a report.
+                Hidden frames are summarized in a note.
         """
 
 class Color:
@@ -124,26 +191,176 @@ class ColorGenerator:
         Get the next color in the sequence.
         """
 
-class Label:
+class Source:
     """
-    Represents a label pointing to some span of code.
+    A source file, decoded and normalized.
+
+    The text of a source has the UTF-8 BOM removed and CRLF line endings
+    replaced by LF. Spans are still given as offsets into the original input
+    and are translated to the normalized text when the report is rendered.
+    """
+
+    def __init__(
+        self,
+        source: str | bytes | Path | TextIO | BinaryIO,
+        *,
+        name: str = NOT_GIVEN,
+        encoding: str = "utf-8",
+        errors: str = "strict",
+        line_offset: int = 0,
+        start_line: int = NOT_GIVEN,
+        column_offset: int = 0,
+        source_map: SourceMap = NOT_GIVEN,
+    ):
+        """
+        Args:
+            source:
+                The source string or bytes, a path or a file-like object.
+                Seekable streams are rewound after reading. Non-seekable
+                streams such as sys.stdin are read only once and the captured
+                text is reused when the same stream is passed again.
+            name:
+                Overrides the path used to identify and display the source,
+                e.g., "<stdin>" or the name of the file the text came from.
+            encoding:
+                Encoding used to decode bytes, binary files and paths. Any
+                codec supported by Python can be used.
+            errors:
+                Error policy for decoding, as in bytes.decode(). E.g.,
+                "strict", "replace" or "ignore".
+            line_offset, start_line:
+                For fragments embedded in a larger file (e.g., a Markdown
+                code fence or a docstring), the number of lines before the
+                fragment or the line in which it starts. Rendered line numbers
+                refer to the host file. Spans are still relative to the
+                fragment.
+            column_offset:
+                Column in which the first line of the fragment starts in the
+                host file.
+            source_map:
+                For generated or transpiled code, maps ranges of the source
+                to ranges of the original files. Labels in mapped ranges are
+                rendered against the original file.
+        """
+
+    @staticmethod
+    def from_cells(
+        cells: list[str],
+        *,
+        cell_name: str = "Cell [{}]",
+        start: int = 1,
+        name: str = "<notebook>",
+    ) -> Source:
+        """
+        Create a notebook source from a list of cell texts.
+
+        Spans can be global, i.e., offsets into the text of all cells joined
+        by newlines, or relative to a single cell when the label path is the
+        name of that cell. Each cell is rendered with its own header.
+
+        Args:
+            cells:
+                The text of each cell.
+            cell_name:
+                Template for the cell names. The "{}" is replaced by the cell
+                number.
+            start:
+                Number of the first cell.
+            name:
+                Path of the notebook itself.
+
+        Raises:
+            ValueError: if there are no cells.
+        """
+
+    @property
+    def cells(self) -> list[Source] | None:
+        """The cells of a notebook source, or None for regular sources."""
+
+    @property
+    def path(self) -> str:
+        """Path of the source file, or a pseudo-path like "<string>"."""
+
+    @property
+    def text(self) -> str:
+        """The decoded and normalized text."""
+
+    @property
+    def source_map(self) -> SourceMap | None:
+        """The source map of generated code."""
+
+    @property
+    def line_offset(self) -> int:
+        """Number of lines before the fragment in the host file."""
+
+    @property
+    def column_offset(self) -> int:
+        """Column of the first line of the fragment in the host file."""
+
+class SourceMap:
+    """
+    Maps ranges of generated code back to the original files.
+
+    Spans inside a generated range are mapped offset by offset when both
+    ranges have the same length. Otherwise, they point to the whole original
+    range. The original files are taken from the report files or loaded from
+    disk, with the encoding of the report, when the report is created.
+    Files that cannot be read raise FileNotFoundError.
     """
 
     def __init__(
+        self, mappings: list[tuple[int, int, str | Path, int, int]] = NOT_GIVEN
+    ):
+        """
+        Args:
+            mappings:
+                A list of (start, end, path, original_start, original_end)
+                tuples. See `add`.
+        """
+
+    def add(
         self,
         start: int,
         end: int,
+        path: str | Path,
+        original_start: int,
+        original_end: int,
+    ):
+        """
+        Map the generated range start..end to original_start..original_end
+        in the file at path.
+        """
+
+    @property
+    def mappings(self) -> list[tuple[int, int, str, int, int]]:
+        """The list of mappings."""
+
+    def __len__(self) -> int: ...
+
+class Label:
+    """
+    Represents a label pointing to some span of code.
+    """
+
+    def __init__(
+        self,
+        start: int | SpanLike,
+        end: int = NOT_GIVEN,
         *,
         path: str | Path = NOT_GIVEN,
         message: str = NOT_GIVEN,
         color: Color = NOT_GIVEN,
         order: int = NOT_GIVEN,
         priority: int = NOT_GIVEN,
+        primary: bool = False,
     ):
         """
         Args:
             start, end:
-                The starting position of the label.
+                The starting position of the label, or a single object with
+                a span. Labels are not bound to a source, so offsets given
+                in bytes or chars are kept as they are and converted to the
+                units of the report when the label is rendered.
             path:
                 The file path for the file pointed by the span. If not given,
                 uses the default file for the repost.
@@ -157,8 +374,101 @@ class Label:
             priority:
                 A number indicating the priority of highlighting this label
                 compared to the other labels in the report.
+            primary:
+                If true, the label marks the main location of the report and
+                is highlighted with the color of the report kind, unless it
+                has a color of its own. When the report also has secondary
+                labels, the message of primary labels is led by a ^ marker,
+                so they stand out even without colors.
+
+        Labels on the span of the report are primary unless another label is
+        explicitly marked as such. Reports without labels point to their own
+        span with the ^ marker alone.
+
+        A label with start == end is an insertion point, see `insertion`.
         """
 
+    @staticmethod
+    def insertion(
+        offset: int,
+        *,
+        path: str | Path = NOT_GIVEN,
+        message: str = NOT_GIVEN,
+        color: Color = NOT_GIVEN,
+        order: int = NOT_GIVEN,
+        priority: int = NOT_GIVEN,
+        primary: bool = False,
+    ) -> Label:
+        """
+        Create a zero-width label pointing between two characters, e.g., to
+        say where a missing token is expected.
+
+        The marker is drawn before the character at offset. Insertion points
+        at the end of a line or at the end of the file are drawn right after
+        the last character of the line, and offsets past the end of the file
+        are drawn there too instead of raising an error. Their message is led by a caret (‸,
+        or ^ with ascii=True), so they are not mistaken for a label on the
+        character at offset. Unlike other labels, insertion points are drawn
+        even without a message.
+        """
+
+    @staticmethod
+    def multi(
+        ranges: list[tuple[int, int]],
+        *,
+        path: str | Path = NOT_GIVEN,
+        message: str = NOT_GIVEN,
+        color: Color = NOT_GIVEN,
+        order: int = NOT_GIVEN,
+        priority: int = NOT_GIVEN,
+        primary: bool = False,
+    ) -> Label:
+        """
+        Create a label pointing to several discontinuous ranges, possibly
+        across lines, that share a single message.
+
+        All ranges are highlighted with the same color and the message is
+        shown once, on the last range of the file. The other ranges lead to
+        it with an ellipsis (…, or ... with ascii=True).
+        """
+
+    @staticmethod
+    def from_ast(
+        node: ast.AST | tokenize.TokenInfo,
+        source: str | bytes | Path | Source,
+        *,
+        byte_indexed: bool = False,
+        path: str | Path = NOT_GIVEN,
+        message: str = NOT_GIVEN,
+        color: Color = NOT_GIVEN,
+        order: int = NOT_GIVEN,
+        priority: int = NOT_GIVEN,
+        primary: bool = False,
+    ) -> Label:
+        """
+        Create a label pointing to an AST node or a token parsed from source.
+
+        AST nodes count columns in UTF-8 bytes and tokens count them in
+        characters. Both are converted to offsets in the original source, in
+        chars or bytes depending on byte_indexed. Nodes without an end
+        position produce insertion points.
+
+        Raises:
+            TypeError: if node has no position.
+        """
+
+    @property
+    def ranges(self) -> list[tuple[int, int]]:
+        """All (start, end) ranges of the label."""
+
+    @property
+    def is_insertion(self) -> bool:
+        """True for zero-width labels."""
+
+    @property
+    def primary(self) -> bool:
+        """True for labels marking the main location of a report."""
+
     def copy(
         self,
         *,
@@ -166,31 +476,109 @@ class Label:
         color: Color = NOT_GIVEN,
         order: int = NOT_GIVEN,
         priority: int = NOT_GIVEN,
+        primary: bool = NOT_GIVEN,
     ) -> Label:
         """
         Copy label possibly replacing some of the attributes.
         """
 
+class Expansion:
+    """
+    A frame of a macro expansion backtrace.
+    """
+
+    name: str
+    call_site: Label
+    definition: Label | None
+
+    def __init__(self, name: str, call_site: Label, definition: Label = NOT_GIVEN):
+        """
+        Args:
+            name:
+                The name of the expanded macro.
+            call_site:
+                Label pointing to the macro invocation. Defaults to the
+                message "in this expansion of `name`".
+            definition:
+                Optional label pointing to the macro definition. Defaults to
+                the message "`name` defined here".
+        """
+
+class Suggestion:
+    """
+    A fix-it suggestion that replaces a span of code.
+    """
+
+    start: int
+    end: int
+    path: str | None
+    replacement: str
+    message: str | None
+    applicability: Literal[
+        "machine-applicable", "maybe-incorrect", "has-placeholders", "unspecified"
+    ]
+
+    def __init__(
+        self,
+        start: int,
+        end: int,
+        replacement: str,
+        *,
+        path: str = NOT_GIVEN,
+        message: str = NOT_GIVEN,
+        applicability: Literal[
+            "machine-applicable", "maybe-incorrect", "has-placeholders", "unspecified"
+        ] = "unspecified",
+    ):
+        """
+        Args:
+            start, end:
+                The span replaced by the suggestion. Use start == end to
+                insert text.
+            replacement:
+                The new text.
+            path:
+                File of the span. Defaults to the report source.
+            message:
+                Help message shown above the patched code. Defaults to
+                "try this".
+            applicability:
+                Whether tools can apply the suggestion automatically.
+                "machine-applicable" suggestions are safe to apply,
+                "maybe-incorrect" ones may not be what the user wants and
+                "has-placeholders" ones contain text that must be filled in.
+        """
+
 class Report:
     """
     A report about errors, warnings and advise for a source code file (or a
     collection of them)
+
+    Reports can be pickled, e.g., to send them between processes, and copied
+    with copy.deepcopy(). Reports built from the same Source object store its
+    text only once.
     """
 
     def __init__(
         self,
-        source: str | Path | TextIO,
-        start: int,
-        end: int,
+        source: str | bytes | Path | TextIO | BinaryIO | Source,
+        start: int | SpanLike,
+        end: int = NOT_GIVEN,
         code: int = NOT_GIVEN,
         message: str = NOT_GIVEN,
         kind: str = "error",
         color: Color = NOT_GIVEN,
         labels: list[Label] = NOT_GIVEN,
-        notes: list[str] = NOT_GIVEN,
-        helps: list[str] = NOT_GIVEN,
+        notes: list[str | Report] = NOT_GIVEN,
+        helps: list[str | Report] = NOT_GIVEN,
         config: Config = NOT_GIVEN,
-        files: list[Path | TextIO] | dict[Path | str, str] = NOT_GIVEN,
+        files: list[Path | TextIO | Source] | dict[Path | str, str | bytes] = NOT_GIVEN,
+        includes: list[Label] = NOT_GIVEN,
+        expansions: list[Expansion] = NOT_GIVEN,
+        suggestions: list[Suggestion] = NOT_GIVEN,
+        name: str = NOT_GIVEN,
+        encoding: str = "utf-8",
+        errors: str = "strict",
     ):
         """
         Args:
@@ -202,21 +590,24 @@ class Report:
                 name of the file in the report.
             start, end:
                 The starting and ending positions of the report in the source
-                code.
+                code. An object with a span (see `SpanLike`) may be given
+                instead.
             code:
                 The main error message for this report.
             message:
                 A message describing the report.
             kind:
-                The kind of the report, e.g., "error", "warning", "advice". If
-                any other custom string is given, a color must be provided.
+                The kind of the report, e.g., "error", "warning", "advice",
+                "note" or "help". If any other custom string is given, a color
+                must be provided.
             color:
                 An optional color for custom report kinds. Cannot be set for
                 standard kinds like "error", "warning" or "advice".
             labels:
                 A list of labels associated with this report.
             notes:
-                A list of notes associated with this report.
+                A list of notes associated with this report. Child reports are
+                rendered as snippets below the main report.
             helps:
                 A list of help messages associated with this report.
             config:
@@ -224,6 +615,83 @@ class Report:
             files:
                 A list of files associated with this report or a dictionary mapping
                 file paths to their contents.
+            includes:
+                The include (or instantiation) stack that led to the source,
+                starting from the innermost frame. Each frame is a label with
+                a path, pointing to the include directive in that file. Frames
+                are rendered as labelled snippets after the labels, or as
+                "included from" lines before the report in compact mode. Files
+                of frames that are not in `files` are read from disk.
+            expansions:
+                The macro expansion backtrace, starting from the innermost
+                expansion. Frames are rendered as secondary labels, or as
+                notes in compact mode.
+            suggestions:
+                Fix-it suggestions rendered as patched code below the report.
+            name:
+                Overrides the path used to identify and display the source.
+                Useful for streams, e.g., name="<stdin>".
+            encoding, errors:
+                Encoding and error policy used to decode the source and files
+                when they are given as bytes, binary files or paths. See the
+                `Source` class for more details.
+        """
+
+    @staticmethod
+    def from_ast(
+        node: ast.AST | tokenize.TokenInfo,
+        source: str | bytes | Path | TextIO | BinaryIO | Source,
+        *,
+        code: int = NOT_GIVEN,
+        message: str = NOT_GIVEN,
+        kind: str = "error",
+        color: Color = NOT_GIVEN,
+        config: Config = NOT_GIVEN,
+        name: str = NOT_GIVEN,
+        encoding: str = "utf-8",
+        errors: str = "strict",
+    ) -> Report:
+        """
+        Create a report spanning an AST node or a token parsed from source.
+
+        See `Label.from_ast` for how positions are converted and `Report` for
+        the other parameters.
+        """
+
+    @staticmethod
+    def from_exception(
+        exc: BaseException,
+        source: str | bytes | Path | TextIO | BinaryIO | Source = NOT_GIVEN,
+        *,
+        code: int = NOT_GIVEN,
+        kind: str = "error",
+        color: Color = NOT_GIVEN,
+        config: Config = NOT_GIVEN,
+        name: str = NOT_GIVEN,
+        encoding: str = "utf-8",
+        errors: str = "strict",
+    ) -> Report:
+        """
+        Create a report from a parser exception.
+
+        SyntaxError, lark's UnexpectedInput and pyparsing's ParseBaseException
+        are handled out of the box, other types need a converter registered
+        with `register_exception_converter`. Sets of expected tokens are
+        shown as help text.
+
+        Args:
+            exc:
+                The exception to convert.
+            source:
+                The parsed source. It may be omitted for SyntaxError, which
+                reads the file from exc.filename or shows the offending line,
+                and for pyparsing exceptions, which keep the parsed string.
+
+        Raises:
+            TypeError: if there is no converter for the exception type.
+            ValueError: if the source is required and not given.
+
+        See `Report` for the other parameters.
         """
 
     def print(self, stderr: bool = False):
@@ -246,23 +714,567 @@ class Report:
 
     def label(
         self,
-        start: int,
-        end: int,
+        start: int | SpanLike,
+        end: int = NOT_GIVEN,
         *,
         path: str | Path = NOT_GIVEN,
         message: str = NOT_GIVEN,
         color: Color = NOT_GIVEN,
         order: int = NOT_GIVEN,
         priority: int = NOT_GIVEN,
+        primary: bool = False,
     ) -> Label:
         """
         Create a new label and add it to the report.
 
-        See the `Label` class for more details on the parameters.
+        Secondary labels without a color get a new unique color, primary
+        labels use the color of the report kind. See the `Label` class for
+        more details on the parameters.
+
+        Objects with a span may be passed instead of start and end. Their
+        offsets are converted to the units of the report.
         """
 
-    def add_note(self, note: str):
-        """Add a note to the report."""
+    def label_text(
+        self,
+        text: str,
+        *,
+        occurrence: int = 1,
+        lines: tuple[int, int] = NOT_GIVEN,
+        path: str | Path = NOT_GIVEN,
+        message: str = NOT_GIVEN,
+        color: Color = NOT_GIVEN,
+        order: int = NOT_GIVEN,
+        priority: int = NOT_GIVEN,
+        primary: bool = False,
+    ) -> Label:
+        """
+        Create a label pointing to an occurrence of text in the source and add
+        it to the report.
+
+        Offsets are computed for the original input, using char or byte
+        indices according to config.byte_indexed.
+
+        Args:
+            text:
+                The text to search for.
+            occurrence:
+                Which non-overlapping occurrence to label, starting at 1.
+            lines:
+                Optional (first, last) range of lines to search, inclusive and
+                numbered as shown in the report.
+            path:
+                File to search in. Defaults to the report source.
+
+        Raises:
+            ValueError: if the text is not found.
 
-    def add_help(self, help: str):
-        """Add a help message to the report."""
+        See the `Label` class for the other parameters.
+        """
+
+    def label_regex(
+        self,
+        pattern: str | re.Pattern[str],
+        *,
+        group: int | str = 0,
+        occurrence: int = 1,
+        lines: tuple[int, int] = NOT_GIVEN,
+        flags: int = 0,
+        path: str | Path = NOT_GIVEN,
+        message: str = NOT_GIVEN,
+        color: Color = NOT_GIVEN,
+        order: int = NOT_GIVEN,
+        priority: int = NOT_GIVEN,
+        primary: bool = False,
+    ) -> Label:
+        """
+        Create a label pointing to a match of a regular expression and add it
+        to the report.
+
+        Args:
+            pattern:
+                A pattern string or a compiled pattern.
+            group:
+                The group of the match to label. Defaults to the whole match.
+            flags:
+                Flags used to compile string patterns.
+
+        Raises:
+            ValueError: if there is no match or the group did not participate
+                in the match.
+
+        See `label_text` for the other parameters.
+        """
+
+    def label_node(
+        self,
+        node: ast.AST | tokenize.TokenInfo,
+        *,
+        path: str | Path = NOT_GIVEN,
+        message: str = NOT_GIVEN,
+        color: Color = NOT_GIVEN,
+        order: int = NOT_GIVEN,
+        priority: int = NOT_GIVEN,
+        primary: bool = False,
+    ) -> Label:
+        """
+        Create a label pointing to an AST node or a token and add it to the
+        report.
+
+        The node must come from parsing the text of the given file, which
+        defaults to the report source. See `Label.from_ast` for details.
+        """
+
+    def add_include(
+        self, label: Label, *, encoding: str = "utf-8", errors: str = "strict"
+    ):
+        """
+        Add a frame to the include stack. The label must have a path.
+
+        If the report does not know the file of the frame, it is read from
+        disk and decoded with the given encoding and error policy. Raises
+        FileNotFoundError if it cannot be read.
+        """
+
+    def included_from(
+        self,
+        path: str | Path,
+        start: int,
+        end: int,
+        *,
+        message: str = NOT_GIVEN,
+        encoding: str = "utf-8",
+        errors: str = "strict",
+    ) -> Label:
+        """
+        Create a new include frame and add it to the include stack.
+
+        Frames should be added from the innermost to the outermost include.
+        Files are loaded as in `add_include`.
+        """
+
+    def add_expansion(self, expansion: Expansion):
+        """
+        Add a frame to the macro expansion backtrace.
+        """
+
+    def expanded_from(
+        self,
+        name: str,
+        start: int,
+        end: int,
+        *,
+        path: str | Path = NOT_GIVEN,
+        definition: Label = NOT_GIVEN,
+    ) -> Expansion:
+        """
+        Create a new macro expansion frame and add it to the backtrace.
+
+        The span points to the call site of the macro. Frames should be added
+        from the innermost to the outermost expansion.
+        """
+
+    def suggest(
+        self,
+        start: int,
+        end: int,
+        replacement: str,
+        *,
+        path: str = NOT_GIVEN,
+        message: str = NOT_GIVEN,
+        applicability: Literal[
+            "machine-applicable", "maybe-incorrect", "has-placeholders", "unspecified"
+        ] = "unspecified",
+    ) -> Suggestion:
+        """
+        Create a new suggestion and add it to the report.
+
+        The patched line(s) are rendered below the report, with removed text
+        struck through in red and inserted text in green. Without colors, the
+        old and new lines are shown as a diff.
+
+        See the `Suggestion` class for more details on the parameters.
+        """
+
+    def add_suggestion(self, suggestion: Suggestion):
+        """
+        Add a suggestion to the report.
+        """
+
+    @property
+    def suggestions(self) -> list[Suggestion]:
+        """
+        Suggestions of the report.
+        """
+
+    def add_note(
+        self,
+        note: str | Report,
+        start: int = NOT_GIVEN,
+        end: int = NOT_GIVEN,
+        *,
+        path: str | Path = NOT_GIVEN,
+        labels: list[Label] = NOT_GIVEN,
+    ):
+        """
+        Add a note to the report.
+
+        Args:
+            note:
+                The note message or a child report.
+            start, end:
+                Optional span the note points to. The note is then rendered
+                as a snippet of its own below the main report.
+            path:
+                File of the span. Defaults to the report source.
+            labels:
+                Labels shown in the note snippet. Labels without a path refer
+                to the file of the note span. If no span is given, the span
+                of the first label is used.
+        """
+
+    def add_help(
+        self,
+        help: str | Report,
+        start: int = NOT_GIVEN,
+        end: int = NOT_GIVEN,
+        *,
+        path: str | Path = NOT_GIVEN,
+        labels: list[Label] = NOT_GIVEN,
+    ):
+        """
+        Add a help message to the report.
+
+        Accepts the same arguments as `add_note`.
+        """
+
+    @property
+    def notes(self) -> list[str | Report]:
+        """
+        Notes of the report. Notes with a span are returned as child reports.
+        """
+
+    @property
+    def helps(self) -> list[str | Report]:
+        """
+        Help messages of the report. Helps with a span are returned as child
+        reports.
+        """
+
+    @notes.setter
+    def notes(self, notes: list[str | Report]): ...
+    @helps.setter
+    def helps(self, helps: list[str | Report]): ...
+    @property
+    def code(self) -> str | None:
+        """The error code of the report."""
+
+    @code.setter
+    def code(self, code: str | None): ...
+    @property
+    def message(self) -> str | None:
+        """The message of the report."""
+
+    @message.setter
+    def message(self, message: str | None): ...
+    @property
+    def kind(self) -> str:
+        """
+        The kind of the report, e.g., "error" or the name of a custom kind.
+
+        Only standard kinds can be assigned, since custom kinds need a color.
+        Use copy(kind=..., color=...) for those.
+        """
+
+    @kind.setter
+    def kind(self, kind: str): ...
+    @property
+    def span(self) -> tuple[int, int]:
+        """The (start, end) span of the report in its source."""
+
+    @span.setter
+    def span(self, span: tuple[int, int]): ...
+    @property
+    def labels(self) -> tuple[Label, ...]:
+        """
+        The labels of the report. Use `add_label`, `extend_labels` or `update`
+        to change them.
+        """
+
+    def copy(
+        self,
+        *,
+        code: str | None = NOT_GIVEN,
+        message: str | None = NOT_GIVEN,
+        kind: str = NOT_GIVEN,
+        color: Color = NOT_GIVEN,
+        span: tuple[int, int] = NOT_GIVEN,
+        labels: list[Label] = NOT_GIVEN,
+        notes: list[str | Report] = NOT_GIVEN,
+        helps: list[str | Report] = NOT_GIVEN,
+        config: Config = NOT_GIVEN,
+    ) -> Report:
+        """
+        Copy report possibly replacing some of the attributes.
+
+        Child reports in notes and helps are shared with the original report.
+        """
+
+    def update(
+        self,
+        *,
+        code: str | None = NOT_GIVEN,
+        message: str | None = NOT_GIVEN,
+        kind: str = NOT_GIVEN,
+        color: Color = NOT_GIVEN,
+        span: tuple[int, int] = NOT_GIVEN,
+        labels: list[Label] = NOT_GIVEN,
+        notes: list[str | Report] = NOT_GIVEN,
+        helps: list[str | Report] = NOT_GIVEN,
+        config: Config = NOT_GIVEN,
+    ) -> None:
+        """
+        Replace some of the attributes in place. Accepts the same arguments as
+        `copy`.
+
+        The report is left unchanged if any of the values is invalid.
+        """
+
+    def extend_labels(self, labels: Iterable[Label]) -> None:
+        """
+        Add several labels to the report.
+        """
+
+    def with_label(
+        self,
+        start: int | SpanLike | Label,
+        end: int = NOT_GIVEN,
+        *,
+        path: str | Path = NOT_GIVEN,
+        message: str = NOT_GIVEN,
+        color: Color = NOT_GIVEN,
+        order: int = NOT_GIVEN,
+        priority: int = NOT_GIVEN,
+        primary: bool = False,
+    ) -> Report:
+        """
+        Add a label and return the report, so calls can be chained.
+
+        Accepts a `Label` or the same arguments as `label`.
+        """
+
+    def with_note(
+        self,
+        note: str | Report,
+        start: int = NOT_GIVEN,
+        end: int = NOT_GIVEN,
+        *,
+        path: str | Path = NOT_GIVEN,
+        labels: list[Label] = NOT_GIVEN,
+    ) -> Report:
+        """
+        Like `add_note`, but returns the report.
+        """
+
+    def with_help(
+        self,
+        help: str | Report,
+        start: int = NOT_GIVEN,
+        end: int = NOT_GIVEN,
+        *,
+        path: str | Path = NOT_GIVEN,
+        labels: list[Label] = NOT_GIVEN,
+    ) -> Report:
+        """
+        Like `add_help`, but returns the report.
+        """
+
+    def with_code(self, code: str | None) -> Report:
+        """
+        Set the error code and return the report.
+        """
+
+    def __eq__(self, other: object) -> bool:
+        """
+        Reports are equal if they have the same contents and configuration.
+        Reports are mutable and cannot be hashed.
+        """
+
+class ReportError(Exception):
+    """
+    An exception carrying one or more reports.
+
+    Raise it to unwind to the code driving a compiler or linter, which can
+    print the reports. Uncaught errors show the rendered reports in the
+    traceback.
+    """
+
+    def __init__(self, reports: Report | Iterable[Report] | BaseExceptionGroup[ReportError]):
+        """
+        Args:
+            reports:
+                A report, a non-empty list of reports or an exception group
+                of ReportErrors, which may be nested. Reports are collected
+                from all the errors in the group.
+        """
+
+    @property
+    def report(self) -> Report:
+        """The first report."""
+
+    @property
+    def reports(self) -> list[Report]:
+        """All reports carried by the exception."""
+
+    def group(self, message: str = NOT_GIVEN) -> ExceptionGroup[ReportError]:
+        """
+        Split the error into an exception group with one ReportError per
+        report, e.g., to handle them with `except*`.
+
+        The message defaults to "N errors". Python versions before 3.11
+        require the exceptiongroup backport.
+        """
+
+    def __str__(self) -> str:
+        """Render the reports with colors disabled."""
+
+class ReportHandler(logging.Handler):
+    """
+    A logging handler that renders records with spans as reports.
+
+    Records logged with extra={"source": ..., "span": ...} become reports
+    with the record message, and reports given as the message or as
+    extra={"report": ...} are rendered as they are. The kind follows the
+    record level: DEBUG and INFO records are shown as advice, WARNING as
+    warnings and ERROR or above as errors. Other records are formatted by
+    the handler formatter, like in a StreamHandler.
+    """
+
+    def __init__(
+        self,
+        level: int | str = logging.NOTSET,
+        *,
+        stream: TextIO = NOT_GIVEN,
+        config: Config = NOT_GIVEN,
+    ):
+        """
+        Args:
+            level:
+                The minimum level of the handled records.
+            stream:
+                Where reports and records are written. Defaults to the
+                current sys.stderr.
+            config:
+                Configuration for reports built from a source and a span.
+                The color option also applies to attached reports. By
+                default, colors are enabled only if the stream is a
+                terminal.
+        """
+
+def register_span_adapter(cls: type, adapter: Callable[[Any], SpanTuple]):
+    """
+    Register a function returning the span of instances of cls, including
+    subclasses. See `SpanLike` for the format of spans.
+    """
+
+Converted = (
+    tuple[SpanTuple | SpanLike, str]
+    | tuple[SpanTuple | SpanLike, str, Iterable[str] | None]
+)
+
+def register_exception_converter(
+    cls: type[BaseException],
+    converter: Callable[[Any], Converted],
+):
+    """
+    Register a function used by `Report.from_exception` to convert instances
+    of cls, including subclasses.
+
+    The converter returns a (span, message) or (span, message, expected)
+    tuple, where span is a SpanLike object or a span tuple and expected is
+    an iterable of the tokens accepted at that position.
+    """
+
+def install_excepthook(*, config: Config = NOT_GIVEN):
+    """
+    Replace sys.excepthook by a hook that renders uncaught exceptions as
+    reports.
+
+    Each frame of the traceback that belongs to a real file is shown as a
+    label, underlining the exact expression on Python 3.11+ and the whole
+    line otherwise. Chained exceptions are rendered in order, ReportErrors
+    print their own reports and SyntaxErrors point to the code that failed
+    to parse. Nothing is written until every report is rendered, and if
+    anything goes wrong, the previously installed hook is used.
+
+    Args:
+        config:
+            Configuration for the reports, including the ones of
+            ReportErrors. By default, ReportErrors keep their own
+            configuration and colors are enabled only if stderr is a
+            terminal.
+    """
+
+def warn(report: Report, category: type[Warning] = UserWarning):
+    """
+    Emit a warning for the report through the warnings module.
+
+    The warning points to the file and line where the report starts, so
+    -W filters and escalation to errors work as for any other warning. The
+    message of the warning is the report message and the report itself is
+    available as its `report` attribute.
+
+    Raises:
+        TypeError: if category is not a Warning subclass.
+    """
+
+def showwarning(
+    message: Warning | str,
+    category: type[Warning],
+    filename: str,
+    lineno: int,
+    file: TextIO | None = None,
+    line: str | None = None,
+):
+    """
+    A replacement for warnings.showwarning that renders warnings emitted by
+    `warn` as warning reports. Other warnings are formatted as usual.
+
+    Enable it with `warnings.showwarning = theseus.showwarning`.
+    """
+
+def apply_edits(
+    edits: list[tuple[str | Path, int, int, str] | Suggestion],
+    *,
+    mode: Literal["text", "write", "diff"] = "text",
+    config: Config = NOT_GIVEN,
+    encoding: str = "utf-8",
+    errors: str = "strict",
+) -> tuple[dict[str, str] | list[str] | str, list[Report]]:
+    """
+    Apply text edits to files.
+
+    Edits are grouped by path and their offsets refer to the file contents,
+    using char or byte indices according to config.byte_indexed. Files are
+    edited without normalization, so BOMs and line endings are preserved.
+
+    Args:
+        edits:
+            A list of (path, start, end, replacement) tuples or suggestions
+            with a path.
+        mode:
+            "text" returns a dictionary mapping paths to their new contents,
+            "write" writes the files and returns the list of written paths
+            and "diff" returns a unified diff of all changes.
+        config:
+            Configuration used for indexing and for the conflict reports.
+        encoding, errors:
+            Encoding and error policy used to read and write the files.
+            The "write" mode only accepts errors="strict", so that bytes
+            that fail to decode are never replaced on disk.
+
+    Returns:
+        A tuple with the result for the chosen mode and a list of reports
+        for conflicting edits. Files with overlapping edits are left
+        untouched.
+    """